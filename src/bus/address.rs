@@ -1,9 +1,9 @@
 // Copyright 2020 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0 OR BSD-3-Clause
 
-use std::cmp::Ordering;
-use std::convert::TryFrom;
-use std::ops::{Add, Sub};
+use core::cmp::Ordering;
+use core::convert::TryFrom;
+use core::ops::{Add, Sub};
 
 /// This trait defines the operations we expect to apply to bus address values.
 pub trait BusAddress:
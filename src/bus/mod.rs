@@ -11,10 +11,10 @@
 mod address;
 mod range;
 
-use std::collections::BTreeMap;
-use std::convert::TryFrom;
-use std::fmt::{Display, Formatter};
-use std::result::Result;
+use alloc::collections::BTreeMap;
+use core::convert::TryFrom;
+use core::fmt::{Display, Formatter};
+use core::result::Result;
 
 use address::BusAddress;
 
@@ -35,7 +35,7 @@ pub enum Error {
 }
 
 impl Display for Error {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         match self {
             Error::DeviceNotFound => write!(f, "device not found"),
             Error::DeviceOverlap => write!(f, "range overlaps with existing device"),
@@ -45,6 +45,7 @@ impl Display for Error {
     }
 }
 
+#[cfg(feature = "std")]
 impl std::error::Error for Error {}
 
 /// A bus that's agnostic to the range address type and device type.
@@ -1,7 +1,7 @@
 // Copyright 2020 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0 OR BSD-3-Clause
 
-use std::cmp::Ordering;
+use core::cmp::Ordering;
 
 use crate::bus::{BusAddress, Error, MmioAddress, PioAddress};
 
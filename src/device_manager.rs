@@ -114,9 +114,9 @@
 //! manager.mmio_write(MmioAddress(0), &vec![b'o', b'k']).unwrap();
 //! ```
 
-use std::fmt::{Display, Formatter};
-use std::result::Result;
-use std::sync::Arc;
+use alloc::sync::Arc;
+use core::fmt::{Display, Formatter};
+use core::result::Result;
 
 use crate::bus::{self, BusManager, MmioAddress, MmioBus, MmioRange, PioAddress, PioBus, PioRange};
 use crate::resources::Resource;
@@ -130,13 +130,14 @@ pub enum Error {
 }
 
 impl Display for Error {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         match self {
             Error::Bus(_) => write!(f, "device_manager: bus error"),
         }
     }
 }
 
+#[cfg(feature = "std")]
 impl std::error::Error for Error {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
@@ -411,6 +412,7 @@ impl IoManager {
 mod tests {
     use super::*;
 
+    #[cfg(feature = "std")]
     use std::error::Error;
     use std::sync::Mutex;
 
@@ -573,6 +575,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg(feature = "std")]
     fn test_error_code() {
         let err = super::Error::Bus(bus::Error::DeviceOverlap);
 
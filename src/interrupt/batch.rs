@@ -0,0 +1,93 @@
+// Copyright (C) 2021 Amazon.com, Inc. or its affiliates.
+// All Rights Reserved.
+
+// SPDX-License-Identifier: Apache-2.0 OR BSD-3-Clause
+
+//! Accumulator for triggering the interrupts of a group once per batch of events.
+
+use alloc::vec::Vec;
+
+use crate::interrupt::{collect_errors, EdgeInterrupt, Error, InterruptSourceGroup, Result};
+
+/// Set of interrupts of a group to be triggered together.
+///
+/// Obtained from `InterruptSourceGroup::trigger_batch_builder()`. Interrupts added several
+/// times to the batch are triggered only once when the batch is flushed.
+pub struct TriggerBatch<'a, G: InterruptSourceGroup> {
+    group: &'a G,
+    indices: Vec<usize>,
+}
+
+impl<'a, G: InterruptSourceGroup> TriggerBatch<'a, G> {
+    pub(crate) fn new(group: &'a G) -> Self {
+        TriggerBatch {
+            group,
+            indices: Vec::new(),
+        }
+    }
+
+    /// Add the index-th interrupt of the group to the batch.
+    pub fn add(&mut self, index: usize) {
+        if !self.indices.contains(&index) {
+            self.indices.push(index);
+        }
+    }
+
+    /// Return whether no interrupt was added to the batch.
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Trigger each interrupt in the batch once, in the order they were first added, and
+    /// empty the batch.
+    ///
+    /// All the interrupts are triggered even if some of them fail. The errors encountered are
+    /// returned as an `Error::Multiple`.
+    pub fn flush(&mut self) -> Result<()>
+    where
+        G::InterruptType: EdgeInterrupt,
+    {
+        let group = self.group;
+        collect_errors(self.indices.drain(..).map(|index| {
+            group
+                .get(index)
+                .ok_or(Error::InvalidConfiguration)?
+                .trigger()
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::Ordering;
+
+    use crate::interrupt::tests::DummyGroup;
+
+    #[test]
+    fn test_trigger_batch() {
+        let mut group = DummyGroup::default();
+        group.allocate_interrupts(3).unwrap();
+
+        let mut batch = group.trigger_batch_builder();
+        assert!(batch.is_empty());
+        batch.add(2);
+        batch.add(0);
+        batch.add(2);
+        batch.flush().unwrap();
+        assert!(batch.is_empty());
+        let triggered = |index| group.get(index).unwrap().triggered.load(Ordering::SeqCst);
+        assert_eq!(triggered(0), 1);
+        assert_eq!(triggered(1), 0);
+        assert_eq!(triggered(2), 1);
+
+        batch.add(1);
+        batch.add(3);
+        match batch.flush() {
+            Err(Error::Multiple(errors)) => assert_eq!(errors.len(), 1),
+            _ => panic!("expected multiple errors"),
+        }
+        assert_eq!(triggered(1), 1);
+    }
+}
@@ -0,0 +1,610 @@
+// Copyright (C) 2021 Amazon.com, Inc. or its affiliates.
+// All Rights Reserved.
+
+// SPDX-License-Identifier: Apache-2.0 OR BSD-3-Clause
+
+//! Wrapper that bounds the number of interrupts allocated in a group.
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::any::Any;
+#[cfg(feature = "configurable")]
+use core::ops::RangeInclusive;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::fs::File;
+
+#[cfg(feature = "configurable")]
+use crate::interrupt::legacy::LegacyInterrupt;
+#[cfg(feature = "msi")]
+use crate::interrupt::msi::{CpuTopology, MsiInterrupt, MsixLayout, TriggerModes};
+#[cfg(feature = "msi")]
+use crate::interrupt::LevelInterrupt;
+#[cfg(feature = "configurable")]
+use crate::interrupt::{ConfigChangeCallback, ConfigSnapshot, ConfigurableInterrupt};
+use crate::interrupt::{
+    EdgeInterrupt, Error, InterruptSourceGroup, LatencyStats, OneShotInterrupt, ReplayPolicy,
+    Result, ThrottleCallback,
+};
+#[cfg(feature = "maskable")]
+use crate::interrupt::{MaskSnapshot, MaskableInterrupt};
+
+/// An `InterruptSourceGroup` that cannot hold more than a maximum number of interrupts.
+///
+/// The maximum is a policy of the VMM, independent of the limits of the backend. Allocations
+/// that would exceed it fail with `Error::InterruptAllocationError` without reaching the
+/// wrapped group. The other operations are forwarded to the wrapped group, which is not
+/// otherwise accessible so that it can't be grown behind the back of the wrapper.
+pub struct BoundedInterruptSourceGroup<G: InterruptSourceGroup> {
+    group: G,
+    max: usize,
+    // Number of interrupts allocated in the group, plus the ones being allocated.
+    reserved: AtomicUsize,
+}
+
+impl<G: InterruptSourceGroup> BoundedInterruptSourceGroup<G> {
+    /// Create a new wrapper around `group` allowing at most `max` interrupts.
+    pub fn with_max(group: G, max: usize) -> Self {
+        let reserved = AtomicUsize::new(group.len());
+        BoundedInterruptSourceGroup {
+            group,
+            max,
+            reserved,
+        }
+    }
+
+    /// Return the maximum number of interrupts in the group.
+    pub fn max_interrupts(&self) -> usize {
+        self.max
+    }
+
+    /// Consume the wrapper and return the underlying group.
+    pub fn into_inner(self) -> G {
+        self.group
+    }
+
+    // Reserve room for up to `size` interrupts, or exactly `size` if not `partial`, and
+    // return the number of interrupts reserved.
+    fn reserve(&self, size: usize, partial: bool) -> Result<usize> {
+        let mut reserved = 0;
+        self.reserved
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |current| {
+                let room = self.max.saturating_sub(current);
+                reserved = if partial { size.min(room) } else { size };
+                if reserved > room {
+                    None
+                } else {
+                    Some(current + reserved)
+                }
+            })
+            .map_err(|_| Error::InterruptAllocationError)?;
+        Ok(reserved)
+    }
+
+    fn release(&self, size: usize) {
+        self.reserved.fetch_sub(size, Ordering::AcqRel);
+    }
+}
+
+impl<G: InterruptSourceGroup> InterruptSourceGroup for BoundedInterruptSourceGroup<G> {
+    type InterruptType = G::InterruptType;
+    type InterruptWrapper = G::InterruptWrapper;
+
+    fn len(&self) -> usize {
+        self.group.len()
+    }
+
+    fn backend_len(&self) -> Result<usize> {
+        self.group.backend_len()
+    }
+
+    fn enable(&self) -> Result<()> {
+        self.group.enable()
+    }
+
+    fn disable(&self) -> Result<()> {
+        self.group.disable()
+    }
+
+    fn is_group_enabled(&self) -> Result<bool> {
+        self.group.is_group_enabled()
+    }
+
+    fn effective_enabled(&self, index: usize) -> bool {
+        self.group.effective_enabled(index)
+    }
+
+    fn fast_disable(&self) -> Result<()> {
+        self.group.fast_disable()
+    }
+
+    fn set_one_shot(&self, index: usize, one_shot: bool) -> Result<()>
+    where
+        Self::InterruptType: OneShotInterrupt,
+    {
+        self.group.set_one_shot(index, one_shot)
+    }
+
+    fn suspend(&self) -> Result<()> {
+        self.group.suspend()
+    }
+
+    fn resume(&self) -> Result<()> {
+        self.group.resume()
+    }
+
+    fn park(&self) -> Result<()> {
+        self.group.park()
+    }
+
+    fn unpark(&self) -> Result<()> {
+        self.group.unpark()
+    }
+
+    fn freeze(&self) -> Result<()> {
+        self.group.freeze()
+    }
+
+    fn thaw(&self) -> Result<()> {
+        self.group.thaw()
+    }
+
+    fn never_fired(&self) -> Result<Vec<usize>> {
+        self.group.never_fired()
+    }
+
+    fn take_fired_since_freeze(&self) -> Result<Vec<usize>> {
+        self.group.take_fired_since_freeze()
+    }
+
+    fn get(&self, index: usize) -> Option<Self::InterruptWrapper> {
+        self.group.get(index)
+    }
+
+    fn with<R>(&self, index: usize, f: impl FnOnce(&Self::InterruptType) -> R) -> Option<R> {
+        self.group.with(index, f)
+    }
+
+    fn allocate_interrupts(&mut self, size: usize) -> Result<()> {
+        self.reserve(size, false)?;
+        let result = self.group.allocate_interrupts(size);
+        if result.is_err() {
+            self.release(size);
+        }
+        result
+    }
+
+    fn allocate_interrupts_best_effort(&mut self, requested: usize) -> Result<usize> {
+        let reserved = self.reserve(requested, true)?;
+        match self.group.allocate_interrupts_best_effort(reserved) {
+            Ok(allocated) => {
+                self.release(reserved - allocated);
+                Ok(allocated)
+            }
+            Err(e) => {
+                self.release(reserved);
+                Err(e)
+            }
+        }
+    }
+
+    fn free_interrupts(&mut self) -> Result<()> {
+        let result = self.group.free_interrupts();
+        self.reserved.store(self.group.len(), Ordering::Release);
+        result
+    }
+
+    #[cfg(feature = "maskable")]
+    fn prepare_free(&self) -> Result<()>
+    where
+        Self::InterruptType: MaskableInterrupt,
+    {
+        self.group.prepare_free()
+    }
+
+    /// Concurrent allocations reserve room for their interrupts before reaching the wrapped
+    /// group, so together they never exceed the maximum.
+    fn allocate_interrupts_shared(&self, size: usize) -> Result<()> {
+        self.reserve(size, false)?;
+        let result = self.group.allocate_interrupts_shared(size);
+        if result.is_err() {
+            self.release(size);
+        }
+        result
+    }
+
+    fn assert_quiescent(&self) -> Result<()> {
+        self.group.assert_quiescent()
+    }
+
+    /// Only the reservations of the freed interrupts are released, leaving the ones of
+    /// concurrent allocations in place.
+    fn free_interrupts_shared(&self) -> Result<()> {
+        let len = self.group.len();
+        let result = self.group.free_interrupts_shared();
+        self.release(len.saturating_sub(self.group.len()));
+        result
+    }
+
+    fn shrink_to_fit(&mut self) -> Result<()> {
+        self.group.shrink_to_fit()
+    }
+
+    fn set_metadata<T: Any + Send + Sync>(&self, index: usize, meta: T) -> Result<()> {
+        self.group.set_metadata(index, meta)
+    }
+
+    fn metadata<T: Any + Send + Sync>(&self, index: usize) -> Option<Arc<T>> {
+        self.group.metadata(index)
+    }
+
+    fn set_coalesce_window(&self, index: usize, window: Duration) -> Result<()> {
+        self.group.set_coalesce_window(index, window)
+    }
+
+    fn set_dedup_window(&self, window: Duration) -> Result<()> {
+        self.group.set_dedup_window(window)
+    }
+
+    fn set_group_budget(&self, per_window: u32, window: Duration) -> Result<()> {
+        self.group.set_group_budget(per_window, window)
+    }
+
+    #[cfg(feature = "std")]
+    fn set_mirror_fd(&self, index: usize, mirror: File) -> Result<()> {
+        self.group.set_mirror_fd(index, mirror)
+    }
+
+    fn set_throttle_callback(&self, cb: ThrottleCallback) -> Result<()> {
+        self.group.set_throttle_callback(cb)
+    }
+
+    fn revalidate(&self) -> Result<Vec<usize>> {
+        self.group.revalidate()
+    }
+
+    #[cfg(feature = "configurable")]
+    fn snapshot_configs(
+        &self,
+    ) -> Result<Vec<ConfigSnapshot<<Self::InterruptType as ConfigurableInterrupt>::Cfg>>>
+    where
+        Self::InterruptType: ConfigurableInterrupt,
+    {
+        self.group.snapshot_configs()
+    }
+
+    #[cfg(feature = "configurable")]
+    fn on_config_change(
+        &self,
+        cb: ConfigChangeCallback<<Self::InterruptType as ConfigurableInterrupt>::Cfg>,
+    ) -> Result<()>
+    where
+        Self::InterruptType: ConfigurableInterrupt,
+    {
+        self.group.on_config_change(cb)
+    }
+
+    #[cfg(feature = "msi")]
+    fn requires_devid(&self) -> bool
+    where
+        Self::InterruptType: MsiInterrupt,
+    {
+        self.group.requires_devid()
+    }
+
+    #[cfg(feature = "configurable")]
+    fn set_gsi_allowlist(&self, allowed: RangeInclusive<u32>) -> Result<()>
+    where
+        Self::InterruptType: LegacyInterrupt,
+    {
+        self.group.set_gsi_allowlist(allowed)
+    }
+
+    #[cfg(feature = "msi")]
+    fn set_cpu_topology(&self, topology: Arc<dyn CpuTopology>) -> Result<()>
+    where
+        Self::InterruptType: MsiInterrupt,
+    {
+        self.group.set_cpu_topology(topology)
+    }
+
+    #[cfg(feature = "msi")]
+    fn set_msix_layout(&self, layout: MsixLayout) -> Result<()>
+    where
+        Self::InterruptType: MsiInterrupt,
+    {
+        self.group.set_msix_layout(layout)
+    }
+
+    #[cfg(feature = "msi")]
+    fn export_blob(&self) -> Result<Vec<u8>>
+    where
+        Self::InterruptType: MsiInterrupt,
+    {
+        self.group.export_blob()
+    }
+
+    #[cfg(feature = "msi")]
+    fn msix_table_size_field(&self) -> u16
+    where
+        Self::InterruptType: MsiInterrupt,
+    {
+        self.group.msix_table_size_field()
+    }
+
+    #[cfg(feature = "msi")]
+    fn msix_layout(&self) -> Option<MsixLayout>
+    where
+        Self::InterruptType: MsiInterrupt,
+    {
+        self.group.msix_layout()
+    }
+
+    #[cfg(feature = "msi")]
+    fn update_data(&self, index: usize, data: u32) -> Result<()>
+    where
+        Self::InterruptType: MsiInterrupt,
+    {
+        self.group.update_data(index, data)
+    }
+
+    #[cfg(feature = "msi")]
+    fn update_address(&self, index: usize, low_addr: u32, high_addr: u32) -> Result<()>
+    where
+        Self::InterruptType: MsiInterrupt,
+    {
+        self.group.update_address(index, low_addr, high_addr)
+    }
+
+    #[cfg(feature = "msi")]
+    fn handle_table_write(&self, offset: u64, data: &[u8]) -> Result<()>
+    where
+        Self::InterruptType: MsiInterrupt,
+    {
+        self.group.handle_table_write(offset, data)
+    }
+
+    #[cfg(feature = "msi")]
+    fn handle_table_read(&self, offset: u64, data: &mut [u8]) -> Result<()>
+    where
+        Self::InterruptType: MsiInterrupt,
+    {
+        self.group.handle_table_read(offset, data)
+    }
+
+    #[cfg(feature = "msi")]
+    fn target_cpu(&self, index: usize) -> Result<u32>
+    where
+        Self::InterruptType: MsiInterrupt,
+    {
+        self.group.target_cpu(index)
+    }
+
+    #[cfg(feature = "msi")]
+    fn sources_targeting(&self, cpu: u32) -> Result<Vec<usize>>
+    where
+        Self::InterruptType: MsiInterrupt,
+    {
+        self.group.sources_targeting(cpu)
+    }
+
+    #[cfg(feature = "msi")]
+    fn evacuate_cpu(&self, cpu: u32, fallback: u32) -> Result<Vec<usize>>
+    where
+        Self::InterruptType: MsiInterrupt,
+    {
+        self.group.evacuate_cpu(cpu, fallback)
+    }
+
+    #[cfg(feature = "msi")]
+    fn vector(&self, index: usize) -> Result<u8>
+    where
+        Self::InterruptType: MsiInterrupt,
+    {
+        self.group.vector(index)
+    }
+
+    #[cfg(feature = "msi")]
+    fn delivery_mode(&self, index: usize) -> Result<u8>
+    where
+        Self::InterruptType: MsiInterrupt,
+    {
+        self.group.delivery_mode(index)
+    }
+
+    #[cfg(feature = "msi")]
+    fn trigger_modes(&self) -> Result<TriggerModes>
+    where
+        Self::InterruptType: MsiInterrupt,
+    {
+        self.group.trigger_modes()
+    }
+
+    #[cfg(feature = "msi")]
+    fn deassert(&self, index: usize) -> Result<()>
+    where
+        Self::InterruptType: MsiInterrupt + LevelInterrupt,
+    {
+        self.group.deassert(index)
+    }
+
+    fn enable_latency_tracking(&self) -> Result<()> {
+        self.group.enable_latency_tracking()
+    }
+
+    fn latency_stats(&self) -> Result<LatencyStats> {
+        self.group.latency_stats()
+    }
+
+    fn doorbell_stride(&self) -> Option<u64> {
+        self.group.doorbell_stride()
+    }
+
+    fn doorbell_write(&self, offset: u64, value: u64) -> Result<()>
+    where
+        Self::InterruptType: EdgeInterrupt,
+    {
+        self.group.doorbell_write(offset, value)
+    }
+
+    #[cfg(feature = "maskable")]
+    fn pending_bit_capacity(&self) -> usize
+    where
+        Self::InterruptType: MaskableInterrupt,
+    {
+        self.group.pending_bit_capacity()
+    }
+
+    fn trigger_all(&self) -> Result<()>
+    where
+        Self::InterruptType: EdgeInterrupt,
+    {
+        self.group.trigger_all()
+    }
+
+    fn replay_fired(&self, fired: &[usize], policy: ReplayPolicy) -> Result<()>
+    where
+        Self::InterruptType: EdgeInterrupt,
+    {
+        self.group.replay_fired(fired, policy)
+    }
+
+    #[cfg(feature = "maskable")]
+    fn mask_all_and_snapshot(&self) -> Result<MaskSnapshot>
+    where
+        Self::InterruptType: MaskableInterrupt,
+    {
+        self.group.mask_all_and_snapshot()
+    }
+
+    #[cfg(feature = "maskable")]
+    fn restore_mask(&self, snapshot: MaskSnapshot) -> Result<()>
+    where
+        Self::InterruptType: MaskableInterrupt,
+    {
+        self.group.restore_mask(snapshot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Mutex;
+
+    use crate::interrupt::tests::{DummyGroup, DummyInterrupt};
+
+    /// Group that can only be grown through `allocate_interrupts_shared()`, failing the
+    /// allocations while `fail` is set.
+    #[derive(Default)]
+    struct SharedGroup {
+        irqs: Mutex<Vec<Arc<DummyInterrupt>>>,
+        fail: AtomicBool,
+    }
+
+    impl InterruptSourceGroup for SharedGroup {
+        type InterruptType = DummyInterrupt;
+        type InterruptWrapper = Arc<DummyInterrupt>;
+
+        fn len(&self) -> usize {
+            self.irqs.lock().unwrap().len()
+        }
+
+        fn enable(&self) -> Result<()> {
+            Ok(())
+        }
+
+        fn disable(&self) -> Result<()> {
+            Ok(())
+        }
+
+        fn get(&self, index: usize) -> Option<Self::InterruptWrapper> {
+            self.irqs.lock().unwrap().get(index).cloned()
+        }
+
+        fn allocate_interrupts(&mut self, size: usize) -> Result<()> {
+            self.allocate_interrupts_shared(size)
+        }
+
+        fn free_interrupts(&mut self) -> Result<()> {
+            self.irqs.lock().unwrap().clear();
+            Ok(())
+        }
+
+        fn allocate_interrupts_shared(&self, size: usize) -> Result<()> {
+            if self.fail.load(Ordering::SeqCst) {
+                return Err(Error::InterruptAllocationError);
+            }
+            let mut irqs = self.irqs.lock().unwrap();
+            irqs.extend((0..size).map(|_| Arc::new(DummyInterrupt::default())));
+            Ok(())
+        }
+
+        fn free_interrupts_shared(&self) -> Result<()> {
+            self.irqs.lock().unwrap().clear();
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_bounded_group() {
+        let mut group = BoundedInterruptSourceGroup::with_max(DummyGroup::default(), 4);
+        assert_eq!(group.max_interrupts(), 4);
+
+        group.allocate_interrupts(3).unwrap();
+        assert!(group.allocate_interrupts(2).is_err());
+        assert!(group.allocate_interrupts(usize::MAX).is_err());
+        assert_eq!(group.len(), 3);
+        assert_eq!(group.allocate_interrupts_best_effort(8).unwrap(), 1);
+        assert!(group.allocate_interrupts_best_effort(1).is_err());
+        assert_eq!(group.len(), 4);
+
+        group.free_interrupts().unwrap();
+        group.allocate_interrupts(4).unwrap();
+    }
+
+    #[test]
+    fn test_bounded_group_shared() {
+        let group = BoundedInterruptSourceGroup::with_max(SharedGroup::default(), 4);
+        group.allocate_interrupts_shared(3).unwrap();
+        assert!(group.allocate_interrupts_shared(2).is_err());
+        assert_eq!(group.len(), 3);
+        // Failed allocations release their reservation.
+        group.group.fail.store(true, Ordering::SeqCst);
+        assert!(group.allocate_interrupts_shared(1).is_err());
+        group.group.fail.store(false, Ordering::SeqCst);
+        group.allocate_interrupts_shared(1).unwrap();
+        assert_eq!(group.len(), 4);
+
+        // Freeing keeps the reservations of allocations still in progress.
+        group.free_interrupts_shared().unwrap();
+        group.allocate_interrupts_shared(3).unwrap();
+        let reserved = group.reserve(1, false).unwrap();
+        group.free_interrupts_shared().unwrap();
+        group.allocate_interrupts_shared(3).unwrap();
+        assert!(group.allocate_interrupts_shared(1).is_err());
+        group.release(reserved);
+        group.allocate_interrupts_shared(1).unwrap();
+
+        // Concurrent allocations never exceed the maximum together.
+        let group = Arc::new(BoundedInterruptSourceGroup::with_max(
+            SharedGroup::default(),
+            8,
+        ));
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let group = group.clone();
+                std::thread::spawn(move || {
+                    (0..4)
+                        .filter(|_| group.allocate_interrupts_shared(1).is_ok())
+                        .count()
+                })
+            })
+            .collect();
+        let allocated: usize = threads.into_iter().map(|t| t.join().unwrap()).sum();
+        assert_eq!(allocated, 8);
+        assert_eq!(group.len(), 8);
+    }
+}
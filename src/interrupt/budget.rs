@@ -0,0 +1,230 @@
+// Copyright (C) 2021 Amazon.com, Inc. or its affiliates.
+// All Rights Reserved.
+
+// SPDX-License-Identifier: Apache-2.0 OR BSD-3-Clause
+
+//! Budget of interrupts shared by all the interrupts of a group.
+
+use alloc::sync::Arc;
+use core::convert::TryFrom;
+use core::sync::atomic::{fence, AtomicU32, AtomicU64, Ordering};
+use core::time::Duration;
+use std::time::Instant;
+
+use crate::interrupt::clock::{InterruptClock, MonotonicClock};
+
+// Layout of the state of the budget: generation of the configuration in the upper 8 bits,
+// index of the current window in the next 24 bits and interrupts delivered in the lower 32.
+const GENERATION_SHIFT: u32 = 56;
+const WINDOW_SHIFT: u32 = 32;
+const WINDOW_MASK: u64 = (1 << 24) - 1;
+
+fn pack(generation: u64, window: u64, count: u32) -> u64 {
+    (generation << GENERATION_SHIFT) | (window << WINDOW_SHIFT) | u64::from(count)
+}
+
+/// Budget of interrupts per time window, shared by the threads triggering the interrupts of a
+/// group.
+///
+/// The budget is meant to back `InterruptSourceGroup::set_group_budget()`. The index of the
+/// current window and the number of interrupts delivered in it are packed in a single atomic,
+/// so that the budget is enforced across threads without locking. The window only moves
+/// forward: an attempt timed in a window older than the current one is denied. The
+/// configuration is protected by a sequence lock, so that it is never observed half updated.
+/// The budget is unlimited until a non zero window is set.
+pub struct GroupBudget {
+    clock: Arc<dyn InterruptClock>,
+    epoch: Instant,
+    // Sequence number of the configuration, odd while it is being updated.
+    seq: AtomicU32,
+    per_window: AtomicU32,
+    window_ns: AtomicU64,
+    state: AtomicU64,
+}
+
+impl GroupBudget {
+    /// Create an unlimited budget reading the time from the system monotonic clock.
+    pub fn new() -> Self {
+        Self::with_clock(Arc::new(MonotonicClock))
+    }
+
+    /// Create an unlimited budget reading the time from `clock`.
+    pub fn with_clock(clock: Arc<dyn InterruptClock>) -> Self {
+        GroupBudget {
+            epoch: clock.now(),
+            clock,
+            seq: AtomicU32::new(0),
+            per_window: AtomicU32::new(0),
+            window_ns: AtomicU64::new(0),
+            state: AtomicU64::new(0),
+        }
+    }
+
+    /// Allow `per_window` interrupts in every time `window`. A zero `window` removes the
+    /// limit.
+    ///
+    /// The interrupts delivered under the previous configuration are not counted against the
+    /// new one.
+    pub fn set_budget(&self, per_window: u32, window: Duration) {
+        let window_ns = u64::try_from(window.as_nanos()).unwrap_or(u64::MAX);
+        // Concurrent updates are serialized by the odd sequence number.
+        let mut seq = self.seq.load(Ordering::Relaxed);
+        loop {
+            if seq & 1 == 1 {
+                core::hint::spin_loop();
+                seq = self.seq.load(Ordering::Relaxed);
+                continue;
+            }
+            match self.seq.compare_exchange_weak(
+                seq,
+                seq.wrapping_add(1),
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(current) => seq = current,
+            }
+        }
+        fence(Ordering::Release);
+        self.per_window.store(per_window, Ordering::Relaxed);
+        self.window_ns.store(window_ns, Ordering::Relaxed);
+        self.seq.store(seq.wrapping_add(2), Ordering::Release);
+    }
+
+    // Return the sequence number, budget per window and window length of a consistent
+    // configuration.
+    fn config(&self) -> (u32, u32, u64) {
+        loop {
+            let seq = self.seq.load(Ordering::Acquire);
+            let per_window = self.per_window.load(Ordering::Relaxed);
+            let window_ns = self.window_ns.load(Ordering::Relaxed);
+            fence(Ordering::Acquire);
+            if seq & 1 == 0 && self.seq.load(Ordering::Relaxed) == seq {
+                return (seq, per_window, window_ns);
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Consume one interrupt from the budget and return whether it must be delivered now.
+    ///
+    /// Interrupts over the budget should be coalesced until the next window.
+    pub fn try_acquire(&self) -> bool {
+        self.try_acquire_at(self.clock.now())
+    }
+
+    fn try_acquire_at(&self, now: Instant) -> bool {
+        let elapsed =
+            u64::try_from(now.saturating_duration_since(self.epoch).as_nanos()).unwrap_or(u64::MAX);
+        loop {
+            let (seq, per_window, window_ns) = self.config();
+            if window_ns == 0 {
+                return true;
+            }
+            if per_window == 0 {
+                return false;
+            }
+            let generation = u64::from((seq >> 1) & 0xff);
+            // Only the low bits of the window index are kept, and indices are compared modulo
+            // 2^24, so an attempt can only be mistaken for a stale one after 2^23 windows.
+            let window = (elapsed / window_ns) & WINDOW_MASK;
+
+            let mut stale_config = false;
+            let result = self
+                .state
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |state| {
+                    let current = (state >> WINDOW_SHIFT) & WINDOW_MASK;
+                    let count = state as u32;
+                    if state >> GENERATION_SHIFT != generation {
+                        // The state either predates the configuration, which starts a new
+                        // window, or the configuration changed since it was read.
+                        stale_config = self.seq.load(Ordering::Acquire) != seq;
+                        return if stale_config {
+                            None
+                        } else {
+                            Some(pack(generation, window, 1))
+                        };
+                    }
+                    let ahead = window.wrapping_sub(current) & WINDOW_MASK;
+                    if ahead == 0 {
+                        if count < per_window {
+                            Some(state + 1)
+                        } else {
+                            None
+                        }
+                    } else if ahead <= WINDOW_MASK / 2 {
+                        Some(pack(generation, window, 1))
+                    } else {
+                        // The attempt is older than the current window.
+                        None
+                    }
+                });
+            if !stale_config {
+                return result.is_ok();
+            }
+        }
+    }
+}
+
+impl Default for GroupBudget {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::thread;
+
+    use crate::interrupt::clock::{InterruptClock, ManualClock};
+
+    #[test]
+    fn test_group_budget() {
+        let clock = Arc::new(ManualClock::new());
+        let budget = Arc::new(GroupBudget::with_clock(clock.clone()));
+        assert!((0..100).all(|_| budget.try_acquire()));
+
+        budget.set_budget(8, Duration::from_millis(1));
+        clock.advance(Duration::from_millis(1));
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let budget = budget.clone();
+                thread::spawn(move || (0..4).filter(|_| budget.try_acquire()).count())
+            })
+            .collect();
+        let delivered: usize = threads.into_iter().map(|t| t.join().unwrap()).sum();
+        assert_eq!(delivered, 8);
+
+        clock.advance(Duration::from_millis(1));
+        assert!(budget.try_acquire());
+
+        budget.set_budget(0, Duration::from_millis(1));
+        clock.advance(Duration::from_millis(1));
+        assert!(!budget.try_acquire());
+
+        budget.set_budget(0, Duration::ZERO);
+        assert!(budget.try_acquire());
+    }
+
+    #[test]
+    fn test_group_budget_forward_only() {
+        let clock = Arc::new(ManualClock::new());
+        let budget = GroupBudget::with_clock(clock.clone());
+        budget.set_budget(2, Duration::from_millis(1));
+        let late = clock.now();
+
+        clock.advance(Duration::from_millis(2));
+        assert!(budget.try_acquire());
+        // An attempt timed in a past window neither gets a budget nor resets the window.
+        assert!(!budget.try_acquire_at(late));
+        assert!(budget.try_acquire());
+        assert!(!budget.try_acquire());
+
+        // A new configuration starts with a full budget.
+        budget.set_budget(1, Duration::from_millis(10));
+        assert!(budget.try_acquire());
+        assert!(!budget.try_acquire());
+    }
+}
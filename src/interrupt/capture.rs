@@ -0,0 +1,375 @@
+// Copyright (C) 2021 Amazon.com, Inc. or its affiliates.
+// All Rights Reserved.
+
+// SPDX-License-Identifier: Apache-2.0 OR BSD-3-Clause
+
+//! Interrupt group recording the interrupts delivered by a device, for testing.
+
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::interrupt::clock::{InterruptClock, MonotonicClock};
+#[cfg(feature = "configurable")]
+use crate::interrupt::ConfigurableInterrupt;
+use crate::interrupt::{
+    collect_errors, AsRefResampleNotifier, EdgeInterrupt, Interrupt, InterruptId,
+    InterruptSourceGroup, InterruptSourceKind, Result,
+};
+#[cfg(feature = "maskable")]
+use crate::interrupt::{MaskableInterrupt, Unmasked};
+
+/// Index of the interrupt and time of each interrupt delivered by a `CaptureInterruptGroup`.
+pub type CaptureLog = Vec<(usize, Instant)>;
+
+struct Capture {
+    clock: Arc<dyn InterruptClock>,
+    log: Mutex<CaptureLog>,
+}
+
+impl Capture {
+    fn record(&self, index: usize) {
+        let now = self.clock.now();
+        self.log
+            .lock()
+            .expect("failed to acquire lock")
+            .push((index, now));
+    }
+}
+
+#[derive(Default)]
+struct CaptureState<C> {
+    #[cfg_attr(not(feature = "configurable"), allow(dead_code))]
+    config: C,
+    enabled: bool,
+    masked: bool,
+    pending: bool,
+    fired: bool,
+}
+
+/// Ack notifier of a `CaptureInterrupt`, returned by its `resample_notifier()`.
+///
+/// No guest consumes the interrupts of a capture group, so the test harness plays the role of
+/// the guest and acknowledges them with `ack()`.
+#[derive(Debug, Default)]
+pub struct AckNotifier {
+    acks: Mutex<u64>,
+    acked: Condvar,
+}
+
+impl AckNotifier {
+    /// Acknowledge the interrupt, waking up the threads waiting in
+    /// `EdgeInterrupt::trigger_and_wait()`.
+    pub fn ack(&self) {
+        *self.acks.lock().expect("failed to acquire lock") += 1;
+        self.acked.notify_all();
+    }
+
+    /// Return the number of acknowledgements of the interrupt.
+    pub fn count(&self) -> u64 {
+        *self.acks.lock().expect("failed to acquire lock")
+    }
+
+    // Wait up to `timeout` for more than `seen` acknowledgements, and return whether they
+    // came in time.
+    fn wait_past(&self, seen: u64, timeout: Duration) -> bool {
+        let acks = self.acks.lock().expect("failed to acquire lock");
+        let (acks, _) = self
+            .acked
+            .wait_timeout_while(acks, timeout, |acks| *acks <= seen)
+            .expect("failed to acquire lock");
+        *acks > seen
+    }
+}
+
+/// Interrupt of a `CaptureInterruptGroup`.
+///
+/// Triggers of a disabled interrupt are dropped, and triggers of a masked interrupt are
+/// delivered when it is unmasked, like for MSI-X interrupts. `trigger_and_wait()` waits for
+/// an acknowledgement through the `AckNotifier` returned by `resample_notifier()`.
+pub struct CaptureInterrupt<C> {
+    id: InterruptId,
+    index: usize,
+    kind: InterruptSourceKind,
+    capture: Arc<Capture>,
+    state: Mutex<CaptureState<C>>,
+    ack: AckNotifier,
+}
+
+impl<C> Interrupt for CaptureInterrupt<C> {
+    fn kind(&self) -> InterruptSourceKind {
+        self.kind
+    }
+
+    fn id(&self) -> InterruptId {
+        self.id
+    }
+
+    fn enable(&self) -> Result<bool> {
+        let mut state = self.state.lock().expect("failed to acquire lock");
+        Ok(!std::mem::replace(&mut state.enabled, true))
+    }
+
+    fn disable(&self) -> Result<bool> {
+        let mut state = self.state.lock().expect("failed to acquire lock");
+        state.pending = false;
+        Ok(std::mem::replace(&mut state.enabled, false))
+    }
+
+    fn is_enabled(&self) -> Result<bool> {
+        Ok(self.state.lock().expect("failed to acquire lock").enabled)
+    }
+}
+
+impl<C> EdgeInterrupt for CaptureInterrupt<C> {
+    fn trigger(&self) -> Result<()> {
+        let mut state = self.state.lock().expect("failed to acquire lock");
+        if state.enabled {
+            state.fired = true;
+            if state.masked {
+                state.pending = true;
+            } else {
+                self.capture.record(self.index);
+            }
+        }
+        Ok(())
+    }
+
+    fn trigger_and_wait(&self, timeout: Duration) -> Result<bool> {
+        let seen = self.ack.count();
+        self.trigger()?;
+        Ok(self.ack.wait_past(seen, timeout))
+    }
+}
+
+impl<C> AsRefResampleNotifier for CaptureInterrupt<C> {
+    type NotifierType = AckNotifier;
+
+    fn resample_notifier(&self) -> &AckNotifier {
+        &self.ack
+    }
+}
+
+#[cfg(feature = "configurable")]
+impl<C: Clone> ConfigurableInterrupt for CaptureInterrupt<C> {
+    type Cfg = C;
+
+    fn update(&self, config: &C) -> Result<()> {
+        self.state.lock().expect("failed to acquire lock").config = config.clone();
+        Ok(())
+    }
+
+    fn validate(&self, _config: &C) -> Result<()> {
+        Ok(())
+    }
+
+    fn get_config(&self) -> Result<C> {
+        Ok(self
+            .state
+            .lock()
+            .expect("failed to acquire lock")
+            .config
+            .clone())
+    }
+}
+
+#[cfg(feature = "maskable")]
+impl<C> MaskableInterrupt for CaptureInterrupt<C> {
+    fn mask(&self) -> Result<bool> {
+        let mut state = self.state.lock().expect("failed to acquire lock");
+        Ok(!std::mem::replace(&mut state.masked, true))
+    }
+
+    fn unmask(&self) -> Result<Unmasked> {
+        let mut state = self.state.lock().expect("failed to acquire lock");
+        state.masked = false;
+        let delivered_pending = std::mem::replace(&mut state.pending, false);
+        if delivered_pending {
+            self.capture.record(self.index);
+        }
+        Ok(Unmasked { delivered_pending })
+    }
+
+    fn is_masked(&self) -> Result<bool> {
+        Ok(self.state.lock().expect("failed to acquire lock").masked)
+    }
+}
+
+/// An `InterruptSourceGroup` recording the interrupts delivered by its interrupts instead of
+/// injecting them into a guest.
+///
+/// Replacing the group of a device with a capture group allows checking the sequence of
+/// interrupts the device delivers under a driver workload, without a hypervisor. The
+/// configuration of the interrupts, of type `C`, is stored but not interpreted.
+pub struct CaptureInterruptGroup<C> {
+    kind: InterruptSourceKind,
+    capture: Arc<Capture>,
+    irqs: Vec<Arc<CaptureInterrupt<C>>>,
+}
+
+impl<C: Default> CaptureInterruptGroup<C> {
+    /// Create a new empty group of interrupts of type `kind`, timestamped with the system
+    /// monotonic clock.
+    pub fn new(kind: InterruptSourceKind) -> Self {
+        Self::with_clock(kind, Arc::new(MonotonicClock))
+    }
+
+    /// Create a new empty group of interrupts of type `kind`, timestamped with `clock`.
+    pub fn with_clock(kind: InterruptSourceKind, clock: Arc<dyn InterruptClock>) -> Self {
+        CaptureInterruptGroup {
+            kind,
+            capture: Arc::new(Capture {
+                clock,
+                log: Mutex::new(Vec::new()),
+            }),
+            irqs: Vec::new(),
+        }
+    }
+
+    /// Return the interrupts delivered since the previous call, in delivery order, and clear
+    /// the log.
+    pub fn take_log(&self) -> CaptureLog {
+        std::mem::take(&mut *self.capture.log.lock().expect("failed to acquire lock"))
+    }
+}
+
+impl<C: Default + Send> InterruptSourceGroup for CaptureInterruptGroup<C> {
+    type InterruptType = CaptureInterrupt<C>;
+    type InterruptWrapper = Arc<CaptureInterrupt<C>>;
+
+    fn len(&self) -> usize {
+        self.irqs.len()
+    }
+
+    fn enable(&self) -> Result<()> {
+        collect_errors(self.irqs.iter().map(|irq| irq.enable().map(|_| ())))
+    }
+
+    fn disable(&self) -> Result<()> {
+        collect_errors(self.irqs.iter().map(|irq| irq.disable().map(|_| ())))
+    }
+
+    fn get(&self, index: usize) -> Option<Self::InterruptWrapper> {
+        self.irqs.get(index).cloned()
+    }
+
+    fn with<R>(&self, index: usize, f: impl FnOnce(&CaptureInterrupt<C>) -> R) -> Option<R> {
+        self.irqs.get(index).map(|irq| f(irq))
+    }
+
+    fn allocate_interrupts(&mut self, size: usize) -> Result<()> {
+        let (kind, capture) = (self.kind, &self.capture);
+        let start = self.irqs.len();
+        self.irqs.extend((start..start + size).map(|index| {
+            Arc::new(CaptureInterrupt {
+                id: InterruptId::next(),
+                index,
+                kind,
+                capture: capture.clone(),
+                state: Mutex::new(CaptureState::default()),
+                ack: AckNotifier::default(),
+            })
+        }));
+        Ok(())
+    }
+
+    fn free_interrupts(&mut self) -> Result<()> {
+        self.irqs.clear();
+        Ok(())
+    }
+
+    fn never_fired(&self) -> Result<Vec<usize>> {
+        Ok(self
+            .irqs
+            .iter()
+            .enumerate()
+            .filter(|(_, irq)| {
+                let state = irq.state.lock().expect("failed to acquire lock");
+                state.enabled && !state.fired
+            })
+            .map(|(index, _)| index)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::thread;
+
+    use crate::interrupt::clock::ManualClock;
+
+    #[test]
+    fn test_capture_group() {
+        let clock = Arc::new(ManualClock::new());
+        let start = clock.now();
+        let mut group =
+            CaptureInterruptGroup::<u32>::with_clock(InterruptSourceKind::MsiX, clock.clone());
+        group.allocate_interrupts(2).unwrap();
+
+        // Disabled interrupts are dropped.
+        group.get(0).unwrap().trigger().unwrap();
+        assert!(group.take_log().is_empty());
+        assert!(group.never_fired().unwrap().is_empty());
+
+        group.enable().unwrap();
+        assert_eq!(group.never_fired().unwrap(), vec![0, 1]);
+        group.get(1).unwrap().trigger().unwrap();
+        assert_eq!(group.never_fired().unwrap(), vec![0]);
+        clock.advance(Duration::from_micros(10));
+        group.get(0).unwrap().trigger().unwrap();
+        assert_eq!(
+            group.take_log(),
+            vec![(1, start), (0, start + Duration::from_micros(10))]
+        );
+        assert!(group.take_log().is_empty());
+
+        #[cfg(feature = "configurable")]
+        {
+            group.get(1).unwrap().update(&0x41).unwrap();
+            assert_eq!(group.get(1).unwrap().get_config().unwrap(), 0x41);
+        }
+
+        #[cfg(feature = "maskable")]
+        {
+            let irq = group.get(0).unwrap();
+            irq.mask().unwrap();
+            irq.trigger().unwrap();
+            group.get(1).unwrap().trigger().unwrap();
+            assert!(irq.unmask().unwrap().delivered_pending);
+            let indices: Vec<_> = group.take_log().into_iter().map(|(i, _)| i).collect();
+            assert_eq!(indices, vec![1, 0]);
+        }
+
+        // Reallocated interrupts were never triggered.
+        group.free_interrupts().unwrap();
+        group.allocate_interrupts(1).unwrap();
+        group.enable().unwrap();
+        assert_eq!(group.never_fired().unwrap(), vec![0]);
+    }
+
+    #[test]
+    fn test_trigger_and_wait() {
+        let mut group = CaptureInterruptGroup::<u32>::new(InterruptSourceKind::MsiX);
+        group.allocate_interrupts(1).unwrap();
+        group.enable().unwrap();
+        let irq = group.get(0).unwrap();
+        assert!(!irq.trigger_and_wait(Duration::from_millis(1)).unwrap());
+        assert_eq!(group.take_log().len(), 1);
+
+        // The harness acknowledges the interrupt once it was delivered.
+        let group = Arc::new(group);
+        let guest = {
+            let group = group.clone();
+            thread::spawn(move || {
+                while group.take_log().is_empty() {
+                    thread::yield_now();
+                }
+                group.get(0).unwrap().resample_notifier().ack();
+            })
+        };
+        assert!(irq.trigger_and_wait(Duration::from_secs(10)).unwrap());
+        guest.join().unwrap();
+        assert_eq!(irq.resample_notifier().count(), 1);
+    }
+}
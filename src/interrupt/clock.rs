@@ -0,0 +1,92 @@
+// Copyright (C) 2021 Amazon.com, Inc. or its affiliates.
+// All Rights Reserved.
+
+// SPDX-License-Identifier: Apache-2.0 OR BSD-3-Clause
+
+//! Time sources used by time dependent interrupt mechanisms.
+//!
+//! Interrupt moderation mechanisms like coalescing depend on time. Implementations of such
+//! mechanisms should read the time through an `InterruptClock` so that tests can control it.
+
+#[cfg(any(test, feature = "test-utils"))]
+use std::sync::Mutex;
+#[cfg(any(test, feature = "test-utils"))]
+use std::time::Duration;
+use std::time::Instant;
+
+/// Trait for time sources used by interrupt implementations.
+pub trait InterruptClock: Send + Sync {
+    /// Return the current time.
+    fn now(&self) -> Instant;
+}
+
+/// Clock that returns the current time of the system monotonic clock.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct MonotonicClock;
+
+impl InterruptClock for MonotonicClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Clock that only advances when explicitly requested.
+///
+/// Allows deterministic testing of time dependent interrupt mechanisms.
+#[cfg(any(test, feature = "test-utils"))]
+#[derive(Debug)]
+pub struct ManualClock {
+    now: Mutex<Instant>,
+}
+
+#[cfg(any(test, feature = "test-utils"))]
+impl ManualClock {
+    /// Create a new clock starting at the current time.
+    pub fn new() -> Self {
+        ManualClock {
+            now: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Advance the clock by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.now.lock().expect("failed to acquire lock");
+        *now += duration;
+    }
+}
+
+#[cfg(any(test, feature = "test-utils"))]
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(any(test, feature = "test-utils"))]
+impl InterruptClock for ManualClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().expect("failed to acquire lock")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manual_clock() {
+        let clock = ManualClock::new();
+        let start = clock.now();
+        assert_eq!(clock.now(), start);
+
+        clock.advance(Duration::from_micros(50));
+        assert_eq!(clock.now() - start, Duration::from_micros(50));
+    }
+
+    #[test]
+    fn test_monotonic_clock() {
+        let clock = MonotonicClock;
+        let start = clock.now();
+        assert!(clock.now() >= start);
+    }
+}
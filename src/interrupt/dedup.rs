@@ -0,0 +1,115 @@
+// Copyright (C) 2021 Amazon.com, Inc. or its affiliates.
+// All Rights Reserved.
+
+// SPDX-License-Identifier: Apache-2.0 OR BSD-3-Clause
+
+//! Deduplication of the triggers of the interrupts in a group.
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+use std::time::Instant;
+
+use crate::interrupt::clock::{InterruptClock, MonotonicClock};
+
+/// Filter dropping the triggers of an interrupt that occur within a time window of the
+/// previous delivered trigger of the same interrupt.
+///
+/// The filter is meant to back `InterruptSourceGroup::set_dedup_window()`. It keeps the time
+/// of the last delivered trigger of each interrupt in an atomic, so checking a trigger takes
+/// no lock and allocates no memory. The filter is disabled until a non zero window is set.
+pub struct DedupFilter {
+    clock: Arc<dyn InterruptClock>,
+    epoch: Instant,
+    window_ns: AtomicU64,
+    last: Vec<AtomicU64>,
+}
+
+impl DedupFilter {
+    /// Create a disabled filter for `len` interrupts reading the time from the system
+    /// monotonic clock.
+    pub fn new(len: usize) -> Self {
+        Self::with_clock(len, Arc::new(MonotonicClock))
+    }
+
+    /// Create a disabled filter for `len` interrupts reading the time from `clock`.
+    pub fn with_clock(len: usize, clock: Arc<dyn InterruptClock>) -> Self {
+        DedupFilter {
+            epoch: clock.now(),
+            clock,
+            window_ns: AtomicU64::new(0),
+            last: (0..len).map(|_| AtomicU64::new(0)).collect(),
+        }
+    }
+
+    /// Set the deduplication window. A zero `window` disables the filter.
+    pub fn set_window(&self, window: Duration) {
+        let window_ns = u64::try_from(window.as_nanos()).unwrap_or(u64::MAX);
+        self.window_ns.store(window_ns, Ordering::Relaxed);
+    }
+
+    /// Return the deduplication window.
+    pub fn window(&self) -> Duration {
+        Duration::from_nanos(self.window_ns.load(Ordering::Relaxed))
+    }
+
+    /// Record a trigger of the index-th interrupt and return whether it must be delivered.
+    ///
+    /// Triggers of interrupts out of the range of the filter are always delivered.
+    pub fn should_deliver(&self, index: usize) -> bool {
+        let window_ns = self.window_ns.load(Ordering::Relaxed);
+        let last = match self.last.get(index) {
+            Some(last) if window_ns != 0 => last,
+            _ => return true,
+        };
+
+        // Times are offset by one so that 0 means that the interrupt was never triggered.
+        let now = u64::try_from(self.clock.now().duration_since(self.epoch).as_nanos())
+            .unwrap_or(u64::MAX - 1)
+            + 1;
+        let prev = last.load(Ordering::Relaxed);
+        if prev != 0 && now.saturating_sub(prev) < window_ns {
+            return false;
+        }
+        // Only one of the concurrent triggers of the interrupt is delivered.
+        last.compare_exchange(prev, now, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::interrupt::clock::ManualClock;
+
+    #[test]
+    fn test_dedup_filter() {
+        let clock = Arc::new(ManualClock::new());
+        let filter = DedupFilter::with_clock(2, clock.clone());
+
+        // Disabled by default.
+        assert_eq!(filter.window(), Duration::ZERO);
+        assert!(filter.should_deliver(0));
+        assert!(filter.should_deliver(0));
+
+        filter.set_window(Duration::from_micros(10));
+        assert!(filter.should_deliver(0));
+        assert!(!filter.should_deliver(0));
+        assert!(filter.should_deliver(1));
+        assert!(filter.should_deliver(2));
+        assert!(filter.should_deliver(2));
+
+        clock.advance(Duration::from_micros(5));
+        assert!(!filter.should_deliver(0));
+        clock.advance(Duration::from_micros(5));
+        assert!(filter.should_deliver(0));
+        assert!(!filter.should_deliver(0));
+        assert!(filter.should_deliver(1));
+
+        filter.set_window(Duration::ZERO);
+        assert!(filter.should_deliver(0));
+    }
+}
@@ -0,0 +1,230 @@
+// Copyright (C) 2021 Amazon.com, Inc. or its affiliates.
+// All Rights Reserved.
+
+// SPDX-License-Identifier: Apache-2.0 OR BSD-3-Clause
+
+//! Interrupt delivered through a fallback path when its primary delivery path fails.
+
+use alloc::sync::Arc;
+
+use crate::interrupt::{
+    call_callback, EdgeInterrupt, Error, Interrupt, InterruptId, InterruptSourceKind, Result,
+};
+
+/// Path through which a `FallbackInterrupt` was delivered.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum DeliveryPath {
+    /// The interrupt was delivered through the primary path.
+    Primary,
+    /// The interrupt was delivered through the fallback path.
+    Fallback,
+}
+
+/// Callback invoked with the path through which a `FallbackInterrupt` was delivered.
+///
+/// With the `panic-safe-callbacks` feature, a panic of the observer is ignored.
+pub type DeliveryObserver = Arc<dyn Fn(DeliveryPath) + Send + Sync>;
+
+/// Edge interrupt that chains a primary and a fallback interrupt.
+///
+/// Triggering the interrupt triggers the primary interrupt (e.g. an irqfd) and, if that fails
+/// with a recoverable error, the fallback interrupt (e.g. an injection from the VMM).
+/// The recoverable errors are `Error::OperationNotSupported`, `Error::InterruptNotTriggered`
+/// and `Error::DeliveryOverflow`; any other error is returned without trying the fallback.
+///
+/// The control plane operations of `Interrupt` are applied to both interrupts, and the
+/// primary interrupt is restored to its previous enable state if the operation fails on the
+/// fallback interrupt. The kind of the interrupt is the kind of the primary interrupt.
+pub struct FallbackInterrupt<P, F> {
+    primary: P,
+    fallback: F,
+    observer: Option<DeliveryObserver>,
+}
+
+impl<P, F> FallbackInterrupt<P, F>
+where
+    P: EdgeInterrupt + Interrupt,
+    F: EdgeInterrupt + Interrupt,
+{
+    /// Create a new interrupt delivered through `primary`, or through `fallback` if that fails.
+    pub fn new(primary: P, fallback: F) -> Self {
+        FallbackInterrupt {
+            primary,
+            fallback,
+            observer: None,
+        }
+    }
+
+    /// Set a callback invoked with the path through which the interrupt was delivered each
+    /// time it is triggered successfully.
+    pub fn with_observer(mut self, observer: DeliveryObserver) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    /// Return the primary interrupt.
+    pub fn primary(&self) -> &P {
+        &self.primary
+    }
+
+    /// Return the fallback interrupt.
+    pub fn fallback(&self) -> &F {
+        &self.fallback
+    }
+
+    fn observe(&self, path: DeliveryPath) {
+        if let Some(observer) = self.observer.as_ref() {
+            call_callback(|| observer(path));
+        }
+    }
+}
+
+impl<P, F> Interrupt for FallbackInterrupt<P, F>
+where
+    P: EdgeInterrupt + Interrupt,
+    F: EdgeInterrupt + Interrupt,
+{
+    fn kind(&self) -> InterruptSourceKind {
+        self.primary.kind()
+    }
+
+    fn id(&self) -> InterruptId {
+        self.primary.id()
+    }
+
+    fn enable(&self) -> Result<bool> {
+        let changed = self.primary.enable()?;
+        if let Err(e) = self.fallback.enable() {
+            // Leave the primary interrupt in its previous state.
+            if changed {
+                let _ = self.primary.disable();
+            }
+            return Err(e);
+        }
+        Ok(changed)
+    }
+
+    fn disable(&self) -> Result<bool> {
+        let changed = self.primary.disable()?;
+        if let Err(e) = self.fallback.disable() {
+            if changed {
+                let _ = self.primary.enable();
+            }
+            return Err(e);
+        }
+        Ok(changed)
+    }
+
+    fn is_enabled(&self) -> Result<bool> {
+        self.primary.is_enabled()
+    }
+}
+
+impl<P, F> EdgeInterrupt for FallbackInterrupt<P, F>
+where
+    P: EdgeInterrupt + Interrupt,
+    F: EdgeInterrupt + Interrupt,
+{
+    fn trigger(&self) -> Result<()> {
+        match self.primary.trigger() {
+            Ok(()) => self.observe(DeliveryPath::Primary),
+            Err(Error::OperationNotSupported)
+            | Err(Error::InterruptNotTriggered)
+            | Err(Error::DeliveryOverflow) => {
+                self.fallback.trigger()?;
+                self.observe(DeliveryPath::Fallback);
+            }
+            Err(e) => return Err(e),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::Ordering;
+    use std::sync::Mutex;
+
+    use crate::interrupt::tests::DummyInterrupt;
+
+    /// Interrupt whose trigger fails with `Error::DeliveryOverflow` if `overflow` is set, or
+    /// with `Error::InvalidConfiguration` otherwise.
+    struct FailingInterrupt {
+        id: InterruptId,
+        overflow: bool,
+    }
+
+    impl FailingInterrupt {
+        fn new(overflow: bool) -> Self {
+            FailingInterrupt {
+                id: InterruptId::next(),
+                overflow,
+            }
+        }
+    }
+
+    impl Interrupt for FailingInterrupt {
+        fn kind(&self) -> InterruptSourceKind {
+            InterruptSourceKind::Msi
+        }
+
+        fn id(&self) -> InterruptId {
+            self.id
+        }
+    }
+
+    impl EdgeInterrupt for FailingInterrupt {
+        fn trigger(&self) -> Result<()> {
+            if self.overflow {
+                Err(Error::DeliveryOverflow)
+            } else {
+                Err(Error::InvalidConfiguration)
+            }
+        }
+    }
+
+    #[test]
+    fn test_fallback_interrupt() {
+        let paths = Arc::new(Mutex::new(Vec::new()));
+        let observer: DeliveryObserver = {
+            let paths = paths.clone();
+            Arc::new(move |path| paths.lock().unwrap().push(path))
+        };
+
+        let irq = FallbackInterrupt::new(DummyInterrupt::default(), DummyInterrupt::default())
+            .with_observer(observer.clone());
+        assert_eq!(irq.kind(), InterruptSourceKind::MsiX);
+        assert!(irq.enable().unwrap());
+        assert!(irq.fallback().is_enabled().unwrap());
+        irq.trigger().unwrap();
+        assert_eq!(irq.primary().triggered.load(Ordering::SeqCst), 1);
+        assert_eq!(irq.fallback().triggered.load(Ordering::SeqCst), 0);
+
+        let irq = FallbackInterrupt::new(FailingInterrupt::new(true), DummyInterrupt::default())
+            .with_observer(observer);
+        irq.trigger().unwrap();
+        assert_eq!(irq.fallback().triggered.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            *paths.lock().unwrap(),
+            vec![DeliveryPath::Primary, DeliveryPath::Fallback]
+        );
+
+        let irq = FallbackInterrupt::new(FailingInterrupt::new(false), DummyInterrupt::default());
+        assert!(irq.trigger().is_err());
+        assert_eq!(irq.fallback().triggered.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_fallback_rollback() {
+        // The enable state of `FailingInterrupt` can't be changed.
+        let irq = FallbackInterrupt::new(DummyInterrupt::default(), FailingInterrupt::new(true));
+        assert!(irq.enable().is_err());
+        assert!(!irq.primary().is_enabled().unwrap());
+
+        irq.primary().enable().unwrap();
+        assert!(irq.disable().is_err());
+        assert!(irq.primary().is_enabled().unwrap());
+    }
+}
@@ -0,0 +1,118 @@
+// Copyright (C) 2021 Amazon.com, Inc. or its affiliates.
+// All Rights Reserved.
+
+// SPDX-License-Identifier: Apache-2.0 OR BSD-3-Clause
+
+//! Group over a fixed set of independently constructed interrupts.
+
+use alloc::sync::Arc;
+
+#[cfg(feature = "msi")]
+use crate::interrupt::msi::{MsiInterrupt, MsixLayout, MsixLayoutCell};
+use crate::interrupt::{collect_errors, Error, Interrupt, InterruptSourceGroup, Result};
+
+/// An `InterruptSourceGroup` over a fixed array of `N` interrupts.
+///
+/// The interrupts are constructed by the caller, possibly by different backends, so the group
+/// does not allocate nor release interrupts: `allocate_interrupts()` and `free_interrupts()`
+/// fail with `Error::OperationNotSupported`.
+pub struct StaticInterruptGroup<I, const N: usize> {
+    irqs: [Arc<I>; N],
+    #[cfg(feature = "msi")]
+    msix_layout: MsixLayoutCell,
+}
+
+impl<I: Interrupt, const N: usize> StaticInterruptGroup<I, N> {
+    /// Create a new group over `irqs`.
+    pub fn new(irqs: [I; N]) -> Self {
+        StaticInterruptGroup {
+            irqs: irqs.map(Arc::new),
+            #[cfg(feature = "msi")]
+            msix_layout: MsixLayoutCell::default(),
+        }
+    }
+}
+
+impl<I, const N: usize> InterruptSourceGroup for StaticInterruptGroup<I, N>
+where
+    I: Interrupt + Send + Sync,
+{
+    type InterruptType = I;
+    type InterruptWrapper = Arc<I>;
+
+    fn len(&self) -> usize {
+        N
+    }
+
+    fn enable(&self) -> Result<()> {
+        collect_errors(self.irqs.iter().map(|irq| irq.enable().map(|_| ())))
+    }
+
+    fn disable(&self) -> Result<()> {
+        collect_errors(self.irqs.iter().map(|irq| irq.disable().map(|_| ())))
+    }
+
+    fn get(&self, index: usize) -> Option<Arc<I>> {
+        self.irqs.get(index).cloned()
+    }
+
+    fn with<R>(&self, index: usize, f: impl FnOnce(&I) -> R) -> Option<R> {
+        self.irqs.get(index).map(|irq| f(irq))
+    }
+
+    fn allocate_interrupts(&mut self, _size: usize) -> Result<()> {
+        Err(Error::OperationNotSupported)
+    }
+
+    fn free_interrupts(&mut self) -> Result<()> {
+        Err(Error::OperationNotSupported)
+    }
+
+    #[cfg(feature = "msi")]
+    fn set_msix_layout(&self, layout: MsixLayout) -> Result<()>
+    where
+        Self::InterruptType: MsiInterrupt,
+    {
+        self.msix_layout.set(layout)
+    }
+
+    #[cfg(feature = "msi")]
+    fn msix_layout(&self) -> Option<MsixLayout>
+    where
+        Self::InterruptType: MsiInterrupt,
+    {
+        self.msix_layout.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::interrupt::tests::DummyInterrupt;
+    use crate::interrupt::InterruptSourceKind;
+
+    #[test]
+    fn test_static_group() {
+        let mut group =
+            StaticInterruptGroup::new([DummyInterrupt::default(), DummyInterrupt::default()]);
+        assert_eq!(group.len(), 2);
+        assert!(!group.is_empty());
+        assert!(group.get(2).is_none());
+        assert_eq!(
+            group.with(1, |irq| irq.kind()),
+            Some(InterruptSourceKind::MsiX)
+        );
+        assert!(group.with(2, |irq| irq.kind()).is_none());
+
+        group.enable().unwrap();
+        assert!(group.get(0).unwrap().is_enabled().unwrap());
+        assert!(group.get(1).unwrap().is_enabled().unwrap());
+        group.disable().unwrap();
+        assert!(!group.get(1).unwrap().is_enabled().unwrap());
+
+        assert!(group.allocate_interrupts(1).is_err());
+        assert!(group.free_interrupts().is_err());
+        assert_eq!(group.len(), 2);
+    }
+}
@@ -0,0 +1,386 @@
+// Copyright (C) 2021 Amazon.com, Inc. or its affiliates.
+// All Rights Reserved.
+
+// SPDX-License-Identifier: Apache-2.0 OR BSD-3-Clause
+
+//! Interrupt wrapper enforcing the state of its group in the trigger path.
+//!
+//! Backends may recycle the resources of released interrupts (e.g. eventfds) for interrupts
+//! allocated later, so a reference to an interrupt obtained before its release could deliver
+//! interrupts to another source. Groups storing their interrupts in a `GuardedInterrupt`
+//! release each of them individually, after which triggering it fails, whatever the path
+//! used to reach it (a wrapper returned by `get()`, `with()` or the methods of the group
+//! acting on all the interrupts).
+
+use alloc::sync::Arc;
+#[cfg(feature = "configurable")]
+use core::ops::RangeInclusive;
+#[cfg(feature = "configurable")]
+use core::sync::atomic::AtomicU64;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+#[cfg(feature = "std")]
+use crate::interrupt::budget::GroupBudget;
+#[cfg(feature = "std")]
+use crate::interrupt::clock::InterruptClock;
+#[cfg(feature = "configurable")]
+use crate::interrupt::ConfigurableInterrupt;
+use crate::interrupt::{
+    EdgeInterrupt, Error, Interrupt, InterruptId, InterruptSourceKind, LevelInterrupt,
+    OneShotInterrupt, Result,
+};
+#[cfg(feature = "maskable")]
+use crate::interrupt::{MaskableInterrupt, Unmasked};
+
+/// State of a group shared by the `GuardedInterrupt`s of its interrupts.
+///
+/// It holds a gate allowing groups to stop the delivery of all their interrupts with a single
+/// store (see `InterruptSourceGroup::fast_disable()`), the budget of interrupts of the
+/// group (see `InterruptSourceGroup::set_group_budget()`) and the interrupt lines the legacy
+/// interrupts of the group may be routed to (see `InterruptSourceGroup::set_gsi_allowlist()`).
+#[derive(Default)]
+pub struct GuardState {
+    closed: AtomicBool,
+    #[cfg(feature = "std")]
+    budget: GroupBudget,
+    // First allowed line in the upper 32 bits, last one in the lower ones.
+    #[cfg(feature = "configurable")]
+    gsi_allowlist: AtomicU64,
+    #[cfg(feature = "configurable")]
+    has_gsi_allowlist: AtomicBool,
+}
+
+impl GuardState {
+    /// Create a new state, with the gate open and an unlimited budget.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a new state whose budget reads the time from `clock`.
+    #[cfg(feature = "std")]
+    pub fn with_clock(clock: Arc<dyn InterruptClock>) -> Self {
+        GuardState {
+            budget: GroupBudget::with_clock(clock),
+            ..Self::default()
+        }
+    }
+
+    /// Return the budget of interrupts shared by the interrupts.
+    #[cfg(feature = "std")]
+    pub fn budget(&self) -> &GroupBudget {
+        &self.budget
+    }
+
+    /// Close the gate: the interrupts sharing the state drop their triggers until it is
+    /// opened.
+    pub fn close_gate(&self) {
+        self.closed.store(true, Ordering::Release);
+    }
+
+    /// Open the gate closed by `close_gate()`.
+    pub fn open_gate(&self) {
+        self.closed.store(false, Ordering::Release);
+    }
+
+    /// Return whether the gate is closed.
+    pub fn is_gate_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    /// Restrict the interrupt lines of the legacy interrupts sharing the state to `allowed`.
+    ///
+    /// The configurations already applied are not checked again.
+    #[cfg(feature = "configurable")]
+    pub fn set_gsi_allowlist(&self, allowed: RangeInclusive<u32>) {
+        let packed = (u64::from(*allowed.start()) << 32) | u64::from(*allowed.end());
+        self.gsi_allowlist.store(packed, Ordering::Release);
+        self.has_gsi_allowlist.store(true, Ordering::Release);
+    }
+
+    /// Return the interrupt lines set by `set_gsi_allowlist()`, if any.
+    #[cfg(feature = "configurable")]
+    pub fn gsi_allowlist(&self) -> Option<RangeInclusive<u32>> {
+        if !self.has_gsi_allowlist.load(Ordering::Acquire) {
+            return None;
+        }
+        let packed = self.gsi_allowlist.load(Ordering::Acquire);
+        Some((packed >> 32) as u32..=packed as u32)
+    }
+}
+
+/// Interrupt wrapper that fails to trigger once released, and drops its triggers while the
+/// gate of its group is closed.
+///
+/// Triggering, asserting or clearing a released interrupt fails with
+/// `Error::InvalidConfiguration`. While the gate is closed, triggering or asserting the
+/// interrupt succeeds without reaching the wrapped interrupt. Triggers over the budget of the
+/// group are deferred: they are coalesced with the next trigger within the budget, or
+/// delivered by `flush_deferred()`. The next trigger can also be discarded with
+/// `suppress_next_trigger()`. The interrupt lines of the configurations, as returned by
+/// `ConfigurableInterrupt::interrupt_line()`, are checked against the allowlist of the
+/// group. The other operations are forwarded to the wrapped interrupt, which is shared with
+/// the other wrappers created from the same `Arc` (e.g. for the aliases of an interrupt).
+pub struct GuardedInterrupt<I> {
+    inner: Arc<I>,
+    state: Arc<GuardState>,
+    released: AtomicBool,
+    fired: AtomicBool,
+    suppressed: AtomicBool,
+    #[cfg(feature = "std")]
+    deferred: AtomicBool,
+}
+
+impl<I> GuardedInterrupt<I> {
+    /// Wrap `inner`, sharing the group state `state`.
+    pub fn new(inner: Arc<I>, state: Arc<GuardState>) -> Self {
+        GuardedInterrupt {
+            inner,
+            state,
+            released: AtomicBool::new(false),
+            fired: AtomicBool::new(false),
+            suppressed: AtomicBool::new(false),
+            #[cfg(feature = "std")]
+            deferred: AtomicBool::new(false),
+        }
+    }
+
+    /// Return the wrapped interrupt.
+    pub fn inner(&self) -> &I {
+        &self.inner
+    }
+
+    /// Return the `Arc` holding the wrapped interrupt.
+    pub fn shared_inner(&self) -> &Arc<I> {
+        &self.inner
+    }
+
+    /// Release the interrupt: all the following triggers fail.
+    pub fn release(&self) {
+        self.released.store(true, Ordering::Release);
+    }
+
+    /// Return whether the interrupt was released.
+    pub fn is_released(&self) -> bool {
+        self.released.load(Ordering::Acquire)
+    }
+
+    /// Return whether a trigger or an assertion was delivered to the wrapped interrupt through
+    /// this wrapper.
+    pub fn has_fired(&self) -> bool {
+        self.fired.load(Ordering::Acquire)
+    }
+
+    /// Discard the next trigger of the interrupt.
+    ///
+    /// This supports `ReplayPolicy::SuppressOneShot`, for devices raising again after a live
+    /// migration an interrupt that was already replayed.
+    pub fn suppress_next_trigger(&self) {
+        self.suppressed.store(true, Ordering::Release);
+    }
+
+    // Check the interrupt line a configuration routes the interrupt to against the policies
+    // of the group.
+    #[cfg(feature = "configurable")]
+    fn check_policy(&self, line: Option<u32>) -> Result<()> {
+        match (line, self.state.gsi_allowlist()) {
+            (Some(line), Some(allowed)) if !allowed.contains(&line) => {
+                Err(Error::InvalidConfiguration)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Record the outcome of a delivery to the wrapped interrupt.
+    fn delivered(&self, result: Result<()>) -> Result<()> {
+        if result.is_ok() && !self.fired.load(Ordering::Relaxed) {
+            self.fired.store(true, Ordering::Release);
+        }
+        result
+    }
+
+    // Return whether a trigger may reach the wrapped interrupt, or an error if the interrupt
+    // was released.
+    fn may_deliver(&self) -> Result<bool> {
+        if self.is_released() {
+            return Err(Error::InvalidConfiguration);
+        }
+        Ok(!self.state.is_gate_closed())
+    }
+}
+
+impl<I: Interrupt> Interrupt for GuardedInterrupt<I> {
+    fn kind(&self) -> InterruptSourceKind {
+        self.inner.kind()
+    }
+
+    fn id(&self) -> InterruptId {
+        self.inner.id()
+    }
+
+    fn enable(&self) -> Result<bool> {
+        self.inner.enable()
+    }
+
+    fn disable(&self) -> Result<bool> {
+        self.inner.disable()
+    }
+
+    fn is_enabled(&self) -> Result<bool> {
+        self.inner.is_enabled()
+    }
+}
+
+impl<I: EdgeInterrupt> GuardedInterrupt<I> {
+    /// Return whether a trigger over the budget of the group is waiting to be delivered.
+    #[cfg(feature = "std")]
+    pub fn is_deferred(&self) -> bool {
+        self.deferred.load(Ordering::Acquire)
+    }
+
+    /// Deliver the trigger deferred because the budget of the group was exhausted, if any.
+    ///
+    /// The trigger is deferred again if the budget is still exhausted.
+    #[cfg(feature = "std")]
+    pub fn flush_deferred(&self) -> Result<()> {
+        if self.deferred.swap(false, Ordering::AcqRel) {
+            self.trigger()
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<I: EdgeInterrupt> EdgeInterrupt for GuardedInterrupt<I> {
+    fn trigger(&self) -> Result<()> {
+        if !self.may_deliver()? {
+            return Ok(());
+        }
+        if self.suppressed.load(Ordering::Relaxed) && self.suppressed.swap(false, Ordering::AcqRel)
+        {
+            return Ok(());
+        }
+        #[cfg(feature = "std")]
+        {
+            if !self.state.budget.try_acquire() {
+                self.deferred.store(true, Ordering::Release);
+                return Ok(());
+            }
+            // A deferred trigger is coalesced with this one.
+            self.deferred.store(false, Ordering::Release);
+        }
+        self.delivered(self.inner.trigger())
+    }
+
+    fn trigger_signal_safe(&self) -> Result<()> {
+        if !self.may_deliver()? {
+            return Ok(());
+        }
+        self.delivered(self.inner.trigger_signal_safe())
+    }
+}
+
+impl<I: LevelInterrupt> LevelInterrupt for GuardedInterrupt<I> {
+    fn assert(&self) -> Result<()> {
+        if !self.may_deliver()? {
+            return Ok(());
+        }
+        self.delivered(self.inner.assert())
+    }
+
+    fn clear(&self) -> Result<()> {
+        self.may_deliver()?;
+        self.inner.clear()
+    }
+}
+
+impl<I: OneShotInterrupt> OneShotInterrupt for GuardedInterrupt<I> {
+    fn set_one_shot(&self, one_shot: bool) -> Result<()> {
+        self.inner.set_one_shot(one_shot)
+    }
+}
+
+#[cfg(feature = "configurable")]
+impl<I> ConfigurableInterrupt for GuardedInterrupt<I>
+where
+    I: ConfigurableInterrupt,
+{
+    type Cfg = I::Cfg;
+
+    fn update(&self, config: &I::Cfg) -> Result<()> {
+        self.check_policy(self.inner.interrupt_line(config))?;
+        self.inner.update(config)
+    }
+
+    fn validate(&self, config: &I::Cfg) -> Result<()> {
+        self.check_policy(self.inner.interrupt_line(config))?;
+        self.inner.validate(config)
+    }
+
+    fn get_config(&self) -> Result<I::Cfg> {
+        self.inner.get_config()
+    }
+
+    fn get_effective_config(&self) -> Result<I::Cfg> {
+        self.inner.get_effective_config()
+    }
+
+    fn config_dirty(&self) -> bool {
+        self.inner.config_dirty()
+    }
+
+    fn clear_dirty(&self) {
+        self.inner.clear_dirty()
+    }
+
+    fn interrupt_line(&self, config: &I::Cfg) -> Option<u32> {
+        self.inner.interrupt_line(config)
+    }
+}
+
+#[cfg(feature = "maskable")]
+impl<I: MaskableInterrupt> MaskableInterrupt for GuardedInterrupt<I> {
+    fn mask(&self) -> Result<bool> {
+        self.inner.mask()
+    }
+
+    fn unmask(&self) -> Result<Unmasked> {
+        self.inner.unmask()
+    }
+
+    fn is_masked(&self) -> Result<bool> {
+        self.inner.is_masked()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::Ordering;
+
+    use crate::interrupt::tests::DummyInterrupt;
+
+    #[test]
+    fn test_guarded_interrupt() {
+        let state = Arc::new(GuardState::new());
+        let inner = Arc::new(DummyInterrupt::default());
+        let irq = GuardedInterrupt::new(inner.clone(), state.clone());
+        let alias = GuardedInterrupt::new(inner.clone(), state.clone());
+        assert_eq!(irq.id(), inner.id());
+
+        irq.trigger().unwrap();
+        state.close_gate();
+        irq.trigger().unwrap();
+        alias.trigger().unwrap();
+        assert_eq!(inner.triggered.load(Ordering::SeqCst), 1);
+        state.open_gate();
+
+        // Releasing a wrapper doesn't affect the other wrappers of the interrupt.
+        alias.release();
+        assert!(matches!(alias.trigger(), Err(Error::InvalidConfiguration)));
+        irq.trigger().unwrap();
+        assert_eq!(inner.triggered.load(Ordering::SeqCst), 2);
+        assert!(irq.has_fired());
+        assert!(!alias.has_fired());
+    }
+}
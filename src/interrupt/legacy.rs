@@ -7,7 +7,9 @@
 //!
 //! Legacy interrupt sources typically include pin based interrupt lines.
 
-use crate::interrupt::ConfigurableInterrupt;
+use core::ops::RangeInclusive;
+
+use crate::interrupt::{ConfigurableInterrupt, Error, Result};
 
 /// Definition for PCI INTx pins.
 #[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd)]
@@ -32,8 +34,67 @@ pub struct LegacyIrqConfig {
     pub interrupt_pin: Option<IntXPin>,
 }
 
+impl LegacyIrqConfig {
+    /// Return the power-on configuration, with no interrupt line nor pin.
+    ///
+    /// Unlike `Default::default()`, this can be used to initialize constants and statics.
+    pub const fn zeroed() -> Self {
+        LegacyIrqConfig {
+            interrupt_line: None,
+            interrupt_pin: None,
+        }
+    }
+
+    /// Return the configuration of an ISA device connected to the interrupt line `irq`.
+    ///
+    /// ISA devices are not connected through a PCI interrupt pin.
+    pub fn isa(irq: u32) -> Self {
+        LegacyIrqConfig {
+            interrupt_line: Some(irq),
+            interrupt_pin: None,
+        }
+    }
+
+    /// Check that the interrupt line is in the `allowed` range.
+    ///
+    /// Implementations with an allowlist (see `InterruptSourceGroup::set_gsi_allowlist()`)
+    /// should call this from `update()` to reject lines outside of it with
+    /// `Error::InvalidConfiguration`. A configuration without an interrupt line is accepted.
+    pub fn check_line(&self, allowed: &RangeInclusive<u32>) -> Result<()> {
+        match self.interrupt_line {
+            Some(line) if !allowed.contains(&line) => Err(Error::InvalidConfiguration),
+            _ => Ok(()),
+        }
+    }
+}
+
 /// Trait for defining properties of Legacy interrupts.
 pub trait LegacyInterrupt: ConfigurableInterrupt<Cfg = LegacyIrqConfig> {}
 
 /// Blanket implementation for Interrupts that use a LegacyIrqConfig.
 impl<T> LegacyInterrupt for T where T: ConfigurableInterrupt<Cfg = LegacyIrqConfig> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_config() {
+        const ZEROED: LegacyIrqConfig = LegacyIrqConfig::zeroed();
+        assert_eq!(ZEROED, LegacyIrqConfig::default());
+
+        let config = LegacyIrqConfig::isa(4);
+        assert_eq!(config.interrupt_line, Some(4));
+        assert_eq!(config.interrupt_pin, None);
+    }
+
+    #[test]
+    fn test_check_line() {
+        let allowed = 16..=23;
+        assert!(LegacyIrqConfig::isa(16).check_line(&allowed).is_ok());
+        assert!(LegacyIrqConfig::isa(23).check_line(&allowed).is_ok());
+        assert!(LegacyIrqConfig::isa(4).check_line(&allowed).is_err());
+        assert!(LegacyIrqConfig::isa(24).check_line(&allowed).is_err());
+        assert!(LegacyIrqConfig::zeroed().check_line(&allowed).is_ok());
+    }
+}
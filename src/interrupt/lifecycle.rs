@@ -0,0 +1,162 @@
+// Copyright (C) 2021 Amazon.com, Inc. or its affiliates.
+// All Rights Reserved.
+
+// SPDX-License-Identifier: Apache-2.0 OR BSD-3-Clause
+
+//! Lifecycle of an interrupt, from its allocation to the delivery of interrupts.
+//!
+//! An interrupt goes through the following states:
+//!
+//! ```text
+//! Unallocated <-> Allocated <-> Enabled <-> Armed
+//!                     ^                       |
+//!                     +-----------------------+
+//! ```
+//!
+//! An interrupt is `Allocated` once its backend resources are registered, `Enabled` once its
+//! generation is enabled and `Armed` once it is also unmasked, i.e. interrupts are delivered to
+//! the guest. Skipping a state (e.g. unmasking an interrupt that is not enabled) or releasing
+//! an interrupt that is not disabled is an invalid transition.
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use crate::interrupt::{Error, Result};
+
+/// State of an interrupt in its lifecycle.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum InterruptState {
+    /// The interrupt has no backend resources.
+    Unallocated,
+    /// The backend resources of the interrupt are registered.
+    Allocated,
+    /// Generation of interrupts is enabled, but the interrupt is masked.
+    Enabled,
+    /// Generation of interrupts is enabled and the interrupt is unmasked.
+    Armed,
+}
+
+impl InterruptState {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            0 => InterruptState::Unallocated,
+            1 => InterruptState::Allocated,
+            2 => InterruptState::Enabled,
+            _ => InterruptState::Armed,
+        }
+    }
+
+    /// Return whether an interrupt can go from this state to `to`.
+    ///
+    /// Staying in the same state is always valid.
+    pub fn can_transition_to(self, to: InterruptState) -> bool {
+        use InterruptState::*;
+
+        matches!(
+            (self, to),
+            (Unallocated, Unallocated | Allocated)
+                | (Allocated, Unallocated | Allocated | Enabled)
+                | (Enabled | Armed, Allocated | Enabled | Armed)
+        )
+    }
+}
+
+/// Thread safe tracker of the state of an interrupt.
+///
+/// Implementations of the interrupt traits can use it to reject operations that are invalid
+/// in the current state of the interrupt with `Error::InvalidStateTransition`.
+#[derive(Debug)]
+pub struct InterruptLifecycle {
+    state: AtomicU8,
+}
+
+impl InterruptLifecycle {
+    /// Create a new tracker of an unallocated interrupt.
+    pub fn new() -> Self {
+        InterruptLifecycle {
+            state: AtomicU8::new(InterruptState::Unallocated as u8),
+        }
+    }
+
+    /// Return the current state of the interrupt.
+    pub fn state(&self) -> InterruptState {
+        InterruptState::from_u8(self.state.load(Ordering::Acquire))
+    }
+
+    /// Move the interrupt to the state `to` and return the previous state.
+    ///
+    /// Fails with `Error::InvalidStateTransition` if the transition is not valid from the
+    /// current state, in which case the state is not changed.
+    pub fn transition(&self, to: InterruptState) -> Result<InterruptState> {
+        self.state
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |current| {
+                if InterruptState::from_u8(current).can_transition_to(to) {
+                    Some(to as u8)
+                } else {
+                    None
+                }
+            })
+            .map(InterruptState::from_u8)
+            .map_err(|current| Error::InvalidStateTransition {
+                from: InterruptState::from_u8(current),
+                to,
+            })
+    }
+}
+
+impl Default for InterruptLifecycle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use InterruptState::*;
+
+    #[test]
+    fn test_valid_lifecycle() {
+        let lifecycle = InterruptLifecycle::new();
+        assert_eq!(lifecycle.state(), Unallocated);
+        for (from, to) in [
+            (Unallocated, Allocated),
+            (Allocated, Enabled),
+            (Enabled, Armed),
+            (Armed, Armed),
+            (Armed, Enabled),
+            (Enabled, Armed),
+            (Armed, Allocated),
+            (Allocated, Unallocated),
+        ] {
+            assert_eq!(lifecycle.transition(to).unwrap(), from);
+        }
+    }
+
+    #[test]
+    fn test_invalid_transitions() {
+        let invalid = [
+            (Unallocated, Enabled),
+            (Unallocated, Armed),
+            (Allocated, Armed),
+            (Enabled, Unallocated),
+            (Armed, Unallocated),
+        ];
+        for (from, to) in invalid {
+            let lifecycle = InterruptLifecycle::new();
+            let path = [Allocated, Enabled, Armed];
+            for state in path.iter().take_while(|_| lifecycle.state() != from) {
+                lifecycle.transition(*state).unwrap();
+            }
+            assert_eq!(lifecycle.state(), from);
+
+            match lifecycle.transition(to) {
+                Err(Error::InvalidStateTransition { from: f, to: t }) => {
+                    assert_eq!((f, t), (from, to))
+                }
+                _ => panic!("expected an invalid transition from {:?} to {:?}", from, to),
+            }
+            assert_eq!(lifecycle.state(), from);
+        }
+    }
+}
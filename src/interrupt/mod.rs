@@ -35,12 +35,55 @@
 //! For simplicity sake, the term `Interrupt Source` is used instead of IRQ to represent both pin-based
 //! interrupts and MSI interrupts.
 
+pub mod batch;
+pub mod bounded;
+#[cfg(feature = "std")]
+pub mod budget;
+#[cfg(all(feature = "std", any(test, feature = "test-utils")))]
+pub mod capture;
+#[cfg(feature = "std")]
+pub mod clock;
+#[cfg(feature = "std")]
+pub mod dedup;
+pub mod fallback;
+pub mod fixed;
+pub mod guard;
+#[cfg(feature = "configurable")]
 pub mod legacy;
+pub mod lifecycle;
+#[cfg(feature = "msi")]
 pub mod msi;
+pub mod named;
+pub mod oneshot;
+#[cfg(feature = "registry")]
+pub mod registry;
+#[cfg(feature = "std")]
+pub mod shared;
+pub mod sparse;
 
-use std::fmt::{self, Display};
-use std::ops::Deref;
-use std::sync::Arc;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::convert::TryFrom;
+use core::fmt::{self, Display};
+use core::ops::Deref;
+#[cfg(feature = "configurable")]
+use core::ops::RangeInclusive;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::fs::File;
+#[cfg(feature = "std")]
+use std::time::Instant;
+
+use batch::TriggerBatch;
+use lifecycle::InterruptState;
+
+#[cfg(feature = "configurable")]
+use legacy::LegacyInterrupt;
+
+#[cfg(feature = "msi")]
+use msi::{CpuTopology, MsiInterrupt, MsixLayout, TriggerMode, TriggerModes};
 
 /// Errors associated with handling interrupts
 #[derive(Debug)]
@@ -51,7 +94,10 @@ pub enum Error {
     /// The specified configuration is not valid.
     InvalidConfiguration,
 
-    /// The interrupt state was not changed.
+    /// The interrupt state could not be changed.
+    ///
+    /// Requesting a state the interrupt is already in is not an error and must not be
+    /// reported through this variant.
     InterruptNotChanged,
 
     /// The interrupt could not be triggered, asserted or de-asserted.
@@ -62,12 +108,63 @@ pub enum Error {
 
     /// An error occured during interrupt release.
     InterruptFreeError,
+
+    /// The interrupt could not be delivered because the underlying notification mechanism is
+    /// saturated.
+    ///
+    /// This typically indicates that the guest is not consuming interrupts (e.g. the counter
+    /// of an eventfd based notifier is about to overflow).
+    DeliveryOverflow,
+
+    /// The interrupt with the given index in its group is still enabled, pending or has an
+    /// unconsumed notification while it is expected to be quiescent.
+    NotQuiescent(usize),
+
+    /// The operation would move the interrupt from a state to another one that cannot follow.
+    InvalidStateTransition {
+        /// State of the interrupt before the operation.
+        from: InterruptState,
+        /// State the operation would move the interrupt to.
+        to: InterruptState,
+    },
+
+    /// Multiple errors occured while operating on several interrupts.
+    Multiple(Vec<Error>),
+}
+
+impl Error {
+    /// Return the Linux errno value corresponding to the error.
+    ///
+    /// The mapping is stable across releases:
+    /// - `OperationNotSupported`: `EOPNOTSUPP` (95);
+    /// - `InvalidConfiguration`: `EINVAL` (22);
+    /// - `InterruptNotChanged`, `InterruptNotTriggered` and `InterruptFreeError`: `EIO` (5);
+    /// - `InterruptAllocationError`: `ENOSPC` (28);
+    /// - `DeliveryOverflow`: `EAGAIN` (11);
+    /// - `NotQuiescent`: `EBUSY` (16);
+    /// - `InvalidStateTransition`: `EPERM` (1);
+    /// - `Multiple`: the errno of the first error, or `EIO` if there is none.
+    pub fn errno(&self) -> i32 {
+        match self {
+            Error::OperationNotSupported => 95,
+            Error::InvalidConfiguration => 22,
+            Error::InterruptNotChanged
+            | Error::InterruptNotTriggered
+            | Error::InterruptFreeError => 5,
+            Error::InterruptAllocationError => 28,
+            Error::DeliveryOverflow => 11,
+            Error::NotQuiescent(_) => 16,
+            Error::InvalidStateTransition { .. } => 1,
+            Error::Multiple(errors) => errors.first().map_or(5, Error::errno),
+        }
+    }
 }
 
+#[cfg(feature = "std")]
 impl std::error::Error for Error {}
 
 /// Reuse std::io::Result to simplify interoperability among crates.
-pub type Result<T> = std::result::Result<T, Error>;
+pub type Result<T> = core::result::Result<T, Error>;
 
 impl Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -79,6 +176,20 @@ impl Display for Error {
             Error::InterruptNotTriggered => write!(f, "the interrupt could not be triggered"),
             Error::InterruptAllocationError => write!(f, "the interrupt could not be allocated"),
             Error::InterruptFreeError => write!(f, "the interrupt could not be released"),
+            Error::DeliveryOverflow => write!(f, "the interrupt delivery mechanism overflowed"),
+            Error::NotQuiescent(index) => write!(f, "the interrupt {} is not quiescent", index),
+            Error::InvalidStateTransition { from, to } => write!(
+                f,
+                "the interrupt cannot go from the {:?} state to the {:?} state",
+                from, to
+            ),
+            Error::Multiple(errors) => {
+                write!(f, "multiple errors occured:")?;
+                for e in errors {
+                    write!(f, " [{}]", e)?;
+                }
+                Ok(())
+            }
         }
     }
 }
@@ -91,7 +202,33 @@ impl Display for Error {
 /// Edge triggered interrupts cannot be shared.
 pub trait EdgeInterrupt {
     /// Signal an interrupt to the guest VM.
+    ///
+    /// Implementations backed by a counter based notifier (e.g. an eventfd) should return
+    /// `Error::DeliveryOverflow` instead of blocking or failing with a generic error when the
+    /// counter is about to saturate. An implementation may also choose to recover by draining
+    /// the counter and re-signaling the notifier.
     fn trigger(&self) -> Result<()>;
+
+    /// Signal an interrupt to the guest VM from a restricted context, e.g. a signal handler.
+    ///
+    /// Implementations must only perform async-signal-safe operations, such as writing to an
+    /// eventfd: no locking and no memory allocation. Interrupt state like masking or coalescing
+    /// is bypassed. Implementations that cannot provide this return
+    /// `Error::OperationNotSupported`.
+    fn trigger_signal_safe(&self) -> Result<()> {
+        Err(Error::OperationNotSupported)
+    }
+
+    /// Signal an interrupt to the guest VM and wait up to `timeout` for the guest to consume
+    /// it.
+    ///
+    /// Returns whether the interrupt was consumed in time. The confirmation is given by the
+    /// ack notifier of the interrupt (see `AsRefResampleNotifier`), so implementations without
+    /// one return `Error::OperationNotSupported`. This is meant for lockstep test harnesses,
+    /// not for the hot path of devices; `capture::CaptureInterrupt` implements it.
+    fn trigger_and_wait(&self, _timeout: Duration) -> Result<bool> {
+        Err(Error::OperationNotSupported)
+    }
 }
 
 /// Trait used by interrupt producers to emulate a level triggered interrupt.
@@ -111,6 +248,26 @@ pub trait LevelInterrupt {
 
     /// Deassert the interrupt line to signal that the device no longer requires service.
     fn clear(&self) -> Result<()>;
+
+    /// Return the moment the guest last acknowledged the interrupt, or `None` if the interrupt
+    /// was never acknowledged or acknowledgements are not tracked by the implementation.
+    ///
+    /// Devices can use this to detect and re-inject lost interrupts.
+    #[cfg(feature = "std")]
+    fn last_ack_instant(&self) -> Option<Instant> {
+        None
+    }
+}
+
+/// Trait used by interrupt producers to inject non-maskable interrupts.
+///
+/// On x86 platforms this is implemented by MSI interrupts through a message using the NMI
+/// delivery mode, or by legacy interrupts connected to the NMI pin of the interrupt controller.
+///
+/// Non-maskable interrupts are delivered regardless of the mask state of the interrupt.
+pub trait NmiInterrupt {
+    /// Inject a non-maskable interrupt into the vCPU identified by `dest_cpu`.
+    fn trigger_nmi(&self, dest_cpu: u32) -> Result<()>;
 }
 
 /// Trait that allows access to a device interrupt status.
@@ -126,6 +283,38 @@ pub trait InterruptStatusChecker {
     fn is_active(&self) -> bool;
 }
 
+/// Type of an interrupt source.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum InterruptSourceKind {
+    /// Legacy pin based interrupt.
+    Legacy,
+    /// PCI MSI interrupt.
+    Msi,
+    /// PCI MSI-X interrupt.
+    MsiX,
+}
+
+/// Opaque identifier of an interrupt, unique in the process for the lifetime of the
+/// interrupt.
+#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct InterruptId(u64);
+
+impl InterruptId {
+    /// Return a new identifier, different from all the identifiers previously returned by
+    /// this function.
+    pub fn next() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        InterruptId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+impl Default for InterruptId {
+    /// Return a new identifier with `InterruptId::next()`.
+    fn default() -> Self {
+        Self::next()
+    }
+}
+
 /// Trait used by interrupt controllers to configure interrupts.
 ///
 /// An object having the `Interrupt` trait is shared between the VMM (which typically implements
@@ -135,13 +324,33 @@ pub trait InterruptStatusChecker {
 ///
 /// Objects implementing this trait are required to have internal mutability.
 pub trait Interrupt {
+    /// Return the type of this interrupt source.
+    fn kind(&self) -> InterruptSourceKind;
+
+    /// Return the identifier of this interrupt.
+    ///
+    /// Implementations should store an identifier returned by `InterruptId::next()` when they
+    /// are created, and wrappers should return the identifier of the wrapped interrupt.
+    fn id(&self) -> InterruptId;
+
     /// Enable generation of interrupts on this line.
-    fn enable(&self) -> Result<()> {
+    ///
+    /// Returns `true` if the interrupt was enabled by this call, or `false` if it was already
+    /// enabled.
+    fn enable(&self) -> Result<bool> {
         Err(Error::OperationNotSupported)
     }
 
     /// Disable generation of interrupts on this line.
-    fn disable(&self) -> Result<()> {
+    ///
+    /// Returns `true` if the interrupt was disabled by this call, or `false` if it was already
+    /// disabled.
+    fn disable(&self) -> Result<bool> {
+        Err(Error::OperationNotSupported)
+    }
+
+    /// Return whether generation of interrupts is enabled on this line.
+    fn is_enabled(&self) -> Result<bool> {
         Err(Error::OperationNotSupported)
     }
 }
@@ -152,27 +361,95 @@ pub trait Interrupt {
 /// the behavior of the interrupt.
 ///
 /// Objects implementing this trait are required to have internal mutability.
+#[cfg(feature = "configurable")]
 pub trait ConfigurableInterrupt: Interrupt {
     /// Type describing the configuration spec of the interrupt.
     type Cfg;
 
     /// Update configuration of the interrupt.
+    ///
+    /// The configuration may originate from an untrusted guest. Implementations must not panic
+    /// on malformed input and should return `Error::InvalidConfiguration` instead.
     fn update(&self, config: &Self::Cfg) -> Result<()>;
 
+    /// Check whether `update()` would accept a configuration, without changing the state of
+    /// the interrupt nor of the backend.
+    ///
+    /// This allows a device to reject a guest write atomically instead of leaving a partially
+    /// applied configuration. Implementations should share the checks with `update()`.
+    fn validate(&self, _config: &Self::Cfg) -> Result<()> {
+        Err(Error::OperationNotSupported)
+    }
+
     /// Returns the current configuration of the interrupt.
     fn get_config(&self) -> Result<Self::Cfg>;
+
+    /// Returns the configuration of the interrupt as applied by the backend.
+    ///
+    /// The backend may coerce a configuration when applying it (e.g. by clearing reserved
+    /// bits), so the effective configuration can differ from the one returned by
+    /// `get_config()`. Backends that can read back the applied configuration should override
+    /// this method; the default implementation returns the cached configuration.
+    ///
+    /// KVM provides no way to read back the GSI routing table, so KVM based implementations
+    /// return the cached configuration. `msi::SinkMsiInterrupt` applies the configuration
+    /// as is, so its cached configuration is the effective one.
+    fn get_effective_config(&self) -> Result<Self::Cfg> {
+        self.get_config()
+    }
+
+    /// Return whether the configuration was changed by `update()` since it was last committed
+    /// to the backend.
+    ///
+    /// Implementations that don't track changes always report the configuration as dirty.
+    fn config_dirty(&self) -> bool {
+        true
+    }
+
+    /// Mark the configuration as committed to the backend.
+    fn clear_dirty(&self) {}
+
+    /// Return the interrupt line `config` routes the interrupt to, if any.
+    ///
+    /// Groups check it against their allowlist of interrupt lines (see
+    /// `InterruptSourceGroup::set_gsi_allowlist()`), so legacy interrupts should return the
+    /// `interrupt_line` of their `LegacyIrqConfig` and wrappers should forward it. The default
+    /// implementation returns `None`, which is always allowed.
+    fn interrupt_line(&self, _config: &Self::Cfg) -> Option<u32> {
+        None
+    }
+}
+
+/// Outcome of unmasking an interrupt through `MaskableInterrupt::unmask()`.
+#[cfg(feature = "maskable")]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Unmasked {
+    /// Whether an interrupt that was pending while the interrupt was masked was delivered.
+    pub delivered_pending: bool,
 }
 
 /// Trait for interrupts that can be masked or unmasked.
 ///
 /// Objects implementing this trait are required to have internal mutability.
+#[cfg(feature = "maskable")]
 pub trait MaskableInterrupt: Interrupt {
     /// Mask the interrupt.  Masked interrupts are remembered but
     /// not delivered.
-    fn mask(&self) -> Result<()>;
+    ///
+    /// Returns `true` if the interrupt was masked by this call, or `false` if it was already
+    /// masked.
+    fn mask(&self) -> Result<bool>;
 
     /// Unmask the interrupt, delivering it if it was pending.
-    fn unmask(&self) -> Result<()>;
+    ///
+    /// The returned `Unmasked` reports whether a pending interrupt was delivered, so that the
+    /// caller can clear the corresponding pending bit.
+    fn unmask(&self) -> Result<Unmasked>;
+
+    /// Return whether the interrupt is currently masked.
+    fn is_masked(&self) -> Result<bool> {
+        Err(Error::OperationNotSupported)
+    }
 }
 
 /// Trait for interrupts that can be auto-retriggered when resampled.
@@ -196,6 +473,18 @@ pub trait AutoRetriggerInterrupt: Interrupt {
     fn set_status_checker(&self, status_checker: Arc<dyn InterruptStatusChecker>) -> Result<()>;
 }
 
+/// Trait for interrupts that can be disabled automatically after their first trigger.
+///
+/// A one-shot interrupt is disabled after a successful trigger, and doesn't fire again until
+/// it is enabled with `Interrupt::enable()`. `oneshot::OneShot` adds this to any edge
+/// interrupt.
+///
+/// Objects implementing this trait are required to have internal mutability.
+pub trait OneShotInterrupt: Interrupt {
+    /// Set whether the interrupt is in one-shot mode.
+    fn set_one_shot(&self, one_shot: bool) -> Result<()>;
+}
+
 /// Trait that provides access to the underlying trigger notification object used by the hypervisor.
 ///
 /// The type of the underlying notification mechanism used by the interrupt is defined by the
@@ -251,6 +540,113 @@ pub trait AsRefResampleNotifier {
     fn resample_notifier(&self) -> &Self::NotifierType;
 }
 
+/// Mask state of all the interrupts in an `InterruptSourceGroup`.
+///
+/// Obtained from `InterruptSourceGroup::mask_all_and_snapshot()` and consumed by
+/// `InterruptSourceGroup::restore_mask()`.
+#[cfg(feature = "maskable")]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MaskSnapshot {
+    masked: Vec<bool>,
+}
+
+#[cfg(feature = "maskable")]
+impl MaskSnapshot {
+    /// Return whether the index-th interrupt was masked when the snapshot was taken, or `None`
+    /// if the index is out of bounds.
+    pub fn is_masked(&self, index: usize) -> Option<bool> {
+        self.masked.get(index).copied()
+    }
+}
+
+/// Configuration and enable state of an interrupt, as returned by
+/// `InterruptSourceGroup::snapshot_configs()`.
+#[cfg(feature = "configurable")]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct ConfigSnapshot<C> {
+    /// Configuration of the interrupt.
+    pub config: C,
+    /// Whether the interrupt generated interrupts (see
+    /// `InterruptSourceGroup::effective_enabled()`).
+    pub enabled: bool,
+}
+
+/// Callback invoked with the index and the new configuration of a reconfigured interrupt.
+///
+/// Registered through `InterruptSourceGroup::on_config_change()`.
+#[cfg(feature = "configurable")]
+pub type ConfigChangeCallback<C> = Arc<dyn Fn(usize, &C) + Send + Sync>;
+
+/// How the interrupts triggered on the source of a live migration are replayed on its
+/// destination.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ReplayPolicy {
+    /// Trigger the interrupts again.
+    ///
+    /// Suitable for devices that don't raise the interrupt again after the restore.
+    ReplayPending,
+    /// Trigger the interrupts again and discard the next trigger of each of them.
+    ///
+    /// Suitable for devices that raise the interrupt again after the restore, so that the
+    /// guest doesn't see it twice.
+    SuppressOneShot,
+}
+
+/// Degree to which a device should reduce the rate of the events generating interrupts.
+#[derive(Copy, Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum ThrottleLevel {
+    /// The device doesn't need to reduce its event rate.
+    None,
+    /// The device should slightly reduce its event rate.
+    Light,
+    /// The device should noticeably reduce its event rate.
+    Moderate,
+    /// The device should reduce its event rate as much as possible.
+    Severe,
+}
+
+/// Callback invoked with the index of an interrupt and the throttle level decided for it.
+///
+/// Registered through `InterruptSourceGroup::set_throttle_callback()`.
+pub type ThrottleCallback = Arc<dyn Fn(usize, ThrottleLevel) + Send + Sync>;
+
+/// Statistics of the latency between triggering an interrupt and its acknowledgement by
+/// the guest.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct LatencyStats {
+    /// Number of latency samples.
+    pub samples: usize,
+    /// Minimum latency.
+    pub min: Duration,
+    /// Median latency.
+    pub p50: Duration,
+    /// 90th percentile latency.
+    pub p90: Duration,
+    /// 99th percentile latency.
+    pub p99: Duration,
+    /// Maximum latency.
+    pub max: Duration,
+}
+
+impl LatencyStats {
+    /// Compute the latency statistics of `samples`.
+    pub fn from_samples(samples: &mut [Duration]) -> Self {
+        if samples.is_empty() {
+            return LatencyStats::default();
+        }
+        samples.sort_unstable();
+        let percentile = |p: usize| samples[(samples.len() - 1) * p / 100];
+        LatencyStats {
+            samples: samples.len(),
+            min: samples[0],
+            p50: percentile(50),
+            p90: percentile(90),
+            p99: percentile(99),
+            max: samples[samples.len() - 1],
+        }
+    }
+}
+
 /// Trait to manage a group of interrupt sources for a device.
 ///
 /// A device may use an InterruptSourceGroup to manage multiple interrupts of the same type.
@@ -258,6 +654,10 @@ pub trait AsRefResampleNotifier {
 /// whole collection of interrupts like enable and disable for cases where enabling or disabling
 /// a single interrupt in the group does not make sense. For example, PCI MSI interrupts must be
 /// enabled as a group.
+///
+/// Implementations must release the backend resources of their interrupts (e.g. unregister
+/// irqfds and free GSIs) when the group is dropped, so that interrupts are not leaked on error
+/// paths that skip `free_interrupts()`.
 pub trait InterruptSourceGroup: Send {
     /// Type of the interrupts contained in this group.
     type InterruptType: Interrupt;
@@ -265,25 +665,1570 @@ pub trait InterruptSourceGroup: Send {
     /// Interrupt Type returned by get
     type InterruptWrapper: Deref<Target = Self::InterruptType>;
 
-    /// Return whether the group manages no interrupts.
-    fn is_empty(&self) -> bool;
-
     /// Get number of interrupt sources managed by the group.
     fn len(&self) -> usize;
 
+    /// Return whether the group manages no interrupts.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get number of interrupt sources currently registered with the backend.
+    ///
+    /// A value different from `len()` indicates that the group and the backend went out of
+    /// sync (e.g. because of a failed teardown).
+    fn backend_len(&self) -> Result<usize> {
+        Err(Error::OperationNotSupported)
+    }
+
     /// Enable the interrupt sources in the group to generate interrupts.
+    ///
+    /// Interrupts are enabled at two levels: an interrupt generates interrupts only if both
+    /// the group and the interrupt itself (see `Interrupt::enable()`) are enabled, e.g. when
+    /// the guest sets the MSI-X Enable bit of a function but disables one of its vectors.
+    /// Groups that track their own enable state report it through `is_group_enabled()` and
+    /// don't change the enable state of their interrupts. Other groups implement this by
+    /// enabling every interrupt in the group.
     fn enable(&self) -> Result<()>;
 
     /// Disable the interrupt sources in the group to generate interrupts.
+    ///
+    /// See `enable()` for the interaction with the enable state of each interrupt.
     fn disable(&self) -> Result<()>;
 
+    /// Return whether the group is enabled, independently of the enable state of its
+    /// interrupts.
+    ///
+    /// Groups without an enable state of their own return `Error::OperationNotSupported`.
+    fn is_group_enabled(&self) -> Result<bool> {
+        Err(Error::OperationNotSupported)
+    }
+
+    /// Return whether the index-th interrupt in the group generates interrupts, i.e. whether
+    /// both the group and the interrupt are enabled.
+    ///
+    /// Groups without an enable state of their own are considered enabled. Returns `false` if
+    /// the index is out of bounds or the enable state cannot be read.
+    fn effective_enabled(&self, index: usize) -> bool {
+        let group_enabled = match self.is_group_enabled() {
+            Ok(enabled) => enabled,
+            Err(Error::OperationNotSupported) => true,
+            Err(_) => false,
+        };
+        group_enabled
+            && match self.get(index) {
+                Some(irq) => irq.is_enabled().unwrap_or(false),
+                None => false,
+            }
+    }
+
+    /// Stop the delivery of all the interrupts in the group with a single group-level gate.
+    ///
+    /// Unlike `disable()`, the interrupts and their backend resources are not touched, which
+    /// makes quiescing large groups (e.g. on device reset) cheap: implementations check the
+    /// gate in the trigger path of each interrupt, so triggers are dropped whether they come
+    /// from a wrapper returned by `get()` or from a method of the group. Delivery resumes once
+    /// the group is enabled again with `enable()`.
+    fn fast_disable(&self) -> Result<()> {
+        Err(Error::OperationNotSupported)
+    }
+
+    /// Set whether the index-th interrupt in the group is a one-shot interrupt, which is
+    /// disabled after each successful trigger until it is enabled again.
+    ///
+    /// Fails with `Error::InvalidConfiguration` if there is no interrupt at `index`.
+    fn set_one_shot(&self, index: usize, one_shot: bool) -> Result<()>
+    where
+        Self::InterruptType: OneShotInterrupt,
+    {
+        self.with(index, |irq| irq.set_one_shot(one_shot))
+            .unwrap_or(Err(Error::InvalidConfiguration))
+    }
+
+    /// Suspend interrupt generation for all the interrupt sources in the group.
+    ///
+    /// Unlike `disable()`, which discards interrupts, interrupts triggered while the group is
+    /// suspended are recorded as pending. This models the behavior of a PCI device in the D3
+    /// power state.
+    fn suspend(&self) -> Result<()> {
+        Err(Error::OperationNotSupported)
+    }
+
+    /// Resume interrupt generation for a suspended group.
+    ///
+    /// Each interrupt source that was triggered while the group was suspended is delivered
+    /// exactly once, regardless of how many times it was triggered.
+    fn resume(&self) -> Result<()> {
+        Err(Error::OperationNotSupported)
+    }
+
+    /// Park the group while the device is being hot-unplugged.
+    ///
+    /// While the group is parked, triggering an interrupt succeeds but nothing is delivered
+    /// and no pending bit is set, and acknowledgements from the guest are drained. Unlike
+    /// `disable()`, the backend resources of the interrupts stay registered, and unlike
+    /// `suspend()`, no interrupt is delivered when the group is unparked.
+    fn park(&self) -> Result<()> {
+        Err(Error::OperationNotSupported)
+    }
+
+    /// Resume interrupt delivery for a parked group.
+    fn unpark(&self) -> Result<()> {
+        Err(Error::OperationNotSupported)
+    }
+
+    /// Start recording the interrupts triggered in the group for live migration.
+    ///
+    /// Interrupts keep being delivered while the group is frozen. The indices of the
+    /// interrupts triggered since the freeze are returned by `take_fired_since_freeze()`, so
+    /// that they can be re-asserted on the migration destination.
+    fn freeze(&self) -> Result<()> {
+        Err(Error::OperationNotSupported)
+    }
+
+    /// Stop recording the interrupts triggered in the group and discard the record.
+    fn thaw(&self) -> Result<()> {
+        Err(Error::OperationNotSupported)
+    }
+
+    /// Return the indices of the enabled interrupts in the group that were never triggered.
+    ///
+    /// An interrupt that stays enabled without ever being triggered often denotes a wiring bug
+    /// of the device (e.g. a wrong queue). Implementations track this with a flag per interrupt
+    /// set on its first trigger, and reset when the interrupt is reallocated.
+    fn never_fired(&self) -> Result<Vec<usize>> {
+        Err(Error::OperationNotSupported)
+    }
+
+    /// Return the indices of the interrupts triggered since the group was frozen or since the
+    /// previous call, ordered by the time they were first triggered, and clear the record.
+    fn take_fired_since_freeze(&self) -> Result<Vec<usize>> {
+        Err(Error::OperationNotSupported)
+    }
+
     /// Return the index-th interrupt in the group, or `None` if the index is out
     /// of bounds.
     fn get(&self, index: usize) -> Option<Self::InterruptWrapper>;
 
+    /// Call `f` with a reference to the index-th interrupt in the group and return its result,
+    /// or `None` if the index is out of bounds.
+    ///
+    /// Unlike `get()`, this doesn't require creating a wrapper, e.g. cloning an `Arc`, so
+    /// groups that can lend a reference to their interrupts should override it.
+    fn with<R>(&self, index: usize, f: impl FnOnce(&Self::InterruptType) -> R) -> Option<R> {
+        self.get(index).map(|irq| f(&irq))
+    }
+
     /// Request new interrupts within this group.
     fn allocate_interrupts(&mut self, size: usize) -> Result<()>;
 
+    /// Request up to `requested` new interrupts within this group.
+    ///
+    /// Unlike `allocate_interrupts()`, which fails if not all the interrupts can be allocated,
+    /// this allocates as many interrupts as possible and returns how many were allocated.
+    /// Fails if not even one interrupt can be allocated.
+    ///
+    /// The default implementation retries `allocate_interrupts()` with decreasing sizes and
+    /// relies on a failed allocation leaving the group unchanged.
+    fn allocate_interrupts_best_effort(&mut self, requested: usize) -> Result<usize> {
+        for size in (1..=requested).rev() {
+            if self.allocate_interrupts(size).is_ok() {
+                return Ok(size);
+            }
+        }
+        Err(Error::InterruptAllocationError)
+    }
+
     /// Release all interrupts within this group.
+    ///
+    /// Calling this before dropping the group is optional; it allows the group to be reused
+    /// and errors from the backend to be reported.
+    ///
+    /// Implementations must tear the interrupts down in the following order:
+    /// 1. disable all the interrupts;
+    /// 2. unmask all the interrupts, discarding pending interrupts;
+    /// 3. unregister the notifier of each interrupt from the highest to the lowest index;
+    /// 4. release the backend resources of the interrupts (e.g. GSIs).
+    ///
+    /// The first two steps are implemented by `prepare_free()`.
     fn free_interrupts(&mut self) -> Result<()>;
+
+    /// Disable and unmask all the interrupts in the group in preparation of their release.
+    ///
+    /// Since the interrupts are disabled first, unmasking them discards any pending
+    /// interrupt instead of delivering it.
+    #[cfg(feature = "maskable")]
+    fn prepare_free(&self) -> Result<()>
+    where
+        Self::InterruptType: MaskableInterrupt,
+    {
+        let irqs = (0..self.len())
+            .map(|index| self.get(index).ok_or(Error::InvalidConfiguration))
+            .collect::<Result<Vec<_>>>()?;
+        for irq in irqs.iter() {
+            irq.disable()?;
+        }
+        for irq in irqs.iter() {
+            irq.unmask()?;
+        }
+        Ok(())
+    }
+
+    /// Request new interrupts within this group without exclusive access to the group.
+    ///
+    /// This allows a group shared between threads (e.g. behind an `Arc`) to be resized while
+    /// interrupts are triggered. Implementations must synchronize internally with `get()`.
+    fn allocate_interrupts_shared(&self, _size: usize) -> Result<()> {
+        Err(Error::OperationNotSupported)
+    }
+
+    /// Check that no interrupt in the group is enabled, pending or has an unconsumed
+    /// notification, which is expected right before releasing the interrupts.
+    ///
+    /// Fails with `Error::NotQuiescent` for the first interrupt that is not quiescent. The
+    /// check is only performed in builds with debug assertions enabled. The default
+    /// implementation only checks the enable state of the interrupts; implementations should
+    /// override it to also check pending interrupts and notifier counters.
+    fn assert_quiescent(&self) -> Result<()> {
+        if !cfg!(debug_assertions) {
+            return Ok(());
+        }
+        for index in 0..self.len() {
+            let irq = self.get(index).ok_or(Error::InvalidConfiguration)?;
+            match irq.is_enabled() {
+                Ok(true) => return Err(Error::NotQuiescent(index)),
+                Ok(false) | Err(Error::OperationNotSupported) => (),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Release all interrupts within this group without exclusive access to the group.
+    ///
+    /// Implementations must synchronize internally with `get()`.
+    fn free_interrupts_shared(&self) -> Result<()> {
+        Err(Error::OperationNotSupported)
+    }
+
+    /// Release the backend resources of the interrupts following the highest enabled
+    /// interrupt in the group and compact the internal storage of the group.
+    ///
+    /// This can be called at any time, e.g. after the guest driver settled on the number of
+    /// interrupts it uses. The default implementation doesn't release anything.
+    fn shrink_to_fit(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Attach opaque user metadata to the index-th interrupt in the group.
+    ///
+    /// The metadata is not interpreted by the group; it is only stored so that it can be
+    /// retrieved later through `metadata()`. Setting new metadata replaces any previous value.
+    fn set_metadata<T: Any + Send + Sync>(&self, _index: usize, _meta: T) -> Result<()> {
+        Err(Error::OperationNotSupported)
+    }
+
+    /// Return the metadata attached to the index-th interrupt in the group, or `None` if no
+    /// metadata of type `T` was attached.
+    fn metadata<T: Any + Send + Sync>(&self, _index: usize) -> Option<Arc<T>> {
+        None
+    }
+
+    /// Configure interrupt coalescing for the index-th interrupt in the group.
+    ///
+    /// Triggers that occur within `window` of each other are merged and delivered to the guest
+    /// as a single interrupt, either when the window expires or on the first trigger after the
+    /// interrupt has been quiescent for a whole window. A zero `window` disables coalescing.
+    fn set_coalesce_window(&self, _index: usize, _window: Duration) -> Result<()> {
+        Err(Error::OperationNotSupported)
+    }
+
+    /// Configure the deduplication of the triggers of the interrupts in the group.
+    ///
+    /// Unlike coalescing, deduplication does not moderate the rate of interrupts: a trigger of
+    /// an interrupt that was already triggered within `window` is dropped, so that a device
+    /// triggering the same interrupt repeatedly does not amplify the interrupts seen by the
+    /// guest. A zero `window`, the default, disables deduplication.
+    fn set_dedup_window(&self, _window: Duration) -> Result<()> {
+        Err(Error::OperationNotSupported)
+    }
+
+    /// Limit the number of interrupts delivered by the whole group to `per_window` in every
+    /// time `window`, whatever the thread triggering them.
+    ///
+    /// Unlike the moderation of individual interrupts, the budget is shared by all the
+    /// interrupts of the group, so that the wakeups of the guest vCPUs by a multi-queue device
+    /// are capped globally. Triggers over the budget are coalesced until the next window.
+    /// A zero `window` removes the budget.
+    fn set_group_budget(&self, _per_window: u32, _window: Duration) -> Result<()> {
+        Err(Error::OperationNotSupported)
+    }
+
+    /// Set an eventfd, owned through `mirror`, signaled every time the index-th interrupt in
+    /// the group is triggered.
+    ///
+    /// This allows external tools to observe the interrupt activity of a device without
+    /// perturbing the delivery of the interrupts. A failure to signal the mirror eventfd must
+    /// not fail the trigger. Setting a new mirror replaces any previous one.
+    #[cfg(feature = "std")]
+    fn set_mirror_fd(&self, _index: usize, _mirror: File) -> Result<()> {
+        Err(Error::OperationNotSupported)
+    }
+
+    /// Register a callback invoked when the interrupt moderation of the group decides that
+    /// an interrupt is over its budget, or back within it.
+    ///
+    /// This allows the device to reduce the rate of the events generating the interrupt,
+    /// e.g. during an interrupt storm, until it is called with `ThrottleLevel::None`.
+    fn set_throttle_callback(&self, _cb: ThrottleCallback) -> Result<()> {
+        Err(Error::OperationNotSupported)
+    }
+
+    /// Check the configuration of every interrupt in the group against the current
+    /// capabilities of the backend.
+    ///
+    /// Returns the indices of the interrupts whose configuration is no longer valid or optimal
+    /// (e.g. after the guest enabled x2APIC or interrupt remapping) and should be reprogrammed.
+    fn revalidate(&self) -> Result<Vec<usize>> {
+        Err(Error::OperationNotSupported)
+    }
+
+    /// Copy the configuration, enable and mask state of the index-th interrupt in the group
+    /// into the dst_index-th interrupt of the `dst` group.
+    ///
+    /// The destination interrupt is masked while its configuration and enable state are
+    /// updated, and unmasked afterwards only if the source interrupt is not masked.
+    #[cfg(all(feature = "configurable", feature = "maskable"))]
+    fn clone_config_into<G>(&self, index: usize, dst: &G, dst_index: usize) -> Result<()>
+    where
+        G: InterruptSourceGroup,
+        Self::InterruptType: ConfigurableInterrupt + MaskableInterrupt,
+        G::InterruptType: ConfigurableInterrupt<Cfg = <Self::InterruptType as ConfigurableInterrupt>::Cfg>
+            + MaskableInterrupt,
+    {
+        let src = self.get(index).ok_or(Error::InvalidConfiguration)?;
+        let dst = dst.get(dst_index).ok_or(Error::InvalidConfiguration)?;
+        let masked = src.is_masked()?;
+        let enabled = src.is_enabled()?;
+
+        dst.mask()?;
+        dst.update(&src.get_config()?)?;
+        if enabled {
+            dst.enable()?;
+        } else {
+            dst.disable()?;
+        }
+        if !masked {
+            dst.unmask()?;
+        }
+        Ok(())
+    }
+
+    /// Recreate every interrupt of the group in the empty group `dst`, with identical
+    /// configuration, enable and mask state.
+    ///
+    /// `dst` is bound to the target backend (e.g. the hypervisor of a forked VM), so this
+    /// allows duplicating the interrupts of a device across backends. On failure, the
+    /// interrupts allocated in `dst` are released. Pending interrupts are not part of the
+    /// group state: devices tracking them (e.g. with `msi::PendingBits`) copy them separately.
+    #[cfg(all(feature = "configurable", feature = "maskable"))]
+    fn clone_into_backend<G>(&self, dst: &mut G) -> Result<()>
+    where
+        G: InterruptSourceGroup,
+        Self::InterruptType: ConfigurableInterrupt + MaskableInterrupt,
+        G::InterruptType: ConfigurableInterrupt<Cfg = <Self::InterruptType as ConfigurableInterrupt>::Cfg>
+            + MaskableInterrupt,
+    {
+        if !dst.is_empty() {
+            return Err(Error::InterruptAllocationError);
+        }
+        dst.allocate_interrupts(self.len())?;
+        let res = (0..self.len()).try_for_each(|index| self.clone_config_into(index, dst, index));
+        if res.is_err() {
+            let _ = dst.free_interrupts();
+        }
+        res
+    }
+
+    /// Return the current configuration and enable state of every interrupt in the group.
+    #[cfg(feature = "configurable")]
+    fn snapshot_configs(
+        &self,
+    ) -> Result<Vec<ConfigSnapshot<<Self::InterruptType as ConfigurableInterrupt>::Cfg>>>
+    where
+        Self::InterruptType: ConfigurableInterrupt,
+    {
+        (0..self.len())
+            .map(|index| {
+                let config = self
+                    .get(index)
+                    .ok_or(Error::InvalidConfiguration)?
+                    .get_config()?;
+                Ok(ConfigSnapshot {
+                    config,
+                    enabled: self.effective_enabled(index),
+                })
+            })
+            .collect()
+    }
+
+    /// Register a callback invoked with the index and the new configuration of an interrupt
+    /// in the group after it was successfully reconfigured through `update()`.
+    ///
+    /// Implementations must invoke the callback without holding any lock protecting the
+    /// configuration, so that the callback may query the group.
+    #[cfg(feature = "configurable")]
+    fn on_config_change(
+        &self,
+        _cb: ConfigChangeCallback<<Self::InterruptType as ConfigurableInterrupt>::Cfg>,
+    ) -> Result<()>
+    where
+        Self::InterruptType: ConfigurableInterrupt,
+    {
+        Err(Error::OperationNotSupported)
+    }
+
+    /// Return whether the backend of the group uses the `devid` field of the MSI
+    /// configuration (e.g. for GIC ITS translation or interrupt remapping).
+    ///
+    /// If it does, `update()` fails with `Error::InvalidConfiguration` for a configuration
+    /// without a device ID.
+    #[cfg(feature = "msi")]
+    fn requires_devid(&self) -> bool
+    where
+        Self::InterruptType: MsiInterrupt,
+    {
+        false
+    }
+
+    /// Restrict the interrupt lines (GSIs) the legacy interrupts in the group may be routed to.
+    ///
+    /// Once set, `update()` fails with `Error::InvalidConfiguration` for a configuration whose
+    /// interrupt line is outside of `allowed` (see `LegacyIrqConfig::check_line()`). This is a
+    /// policy control for isolating devices from each other in a sandboxed VMM, not a
+    /// feature of the interrupt controllers.
+    #[cfg(feature = "configurable")]
+    fn set_gsi_allowlist(&self, _allowed: RangeInclusive<u32>) -> Result<()>
+    where
+        Self::InterruptType: LegacyInterrupt,
+    {
+        Err(Error::OperationNotSupported)
+    }
+
+    /// Set the vCPU topology against which the destination of the MSI interrupts in the group
+    /// is validated.
+    ///
+    /// Once set, `update()` fails with `Error::InvalidConfiguration` for a configuration
+    /// routing an interrupt to an offline vCPU.
+    #[cfg(feature = "msi")]
+    fn set_cpu_topology(&self, _topology: Arc<dyn CpuTopology>) -> Result<()>
+    where
+        Self::InterruptType: MsiInterrupt,
+    {
+        Err(Error::OperationNotSupported)
+    }
+
+    /// Set the location of the MSI-X table and PBA of the device exposing the interrupts of
+    /// the group.
+    ///
+    /// The group doesn't interpret the layout; it is stored so that the emitter of the MSI-X
+    /// capability can read it from `msix_layout()`. Fails with `Error::InvalidConfiguration`
+    /// if `MsixLayout::check()` fails.
+    #[cfg(feature = "msi")]
+    fn set_msix_layout(&self, _layout: MsixLayout) -> Result<()>
+    where
+        Self::InterruptType: MsiInterrupt,
+    {
+        Err(Error::OperationNotSupported)
+    }
+
+    /// Export the configuration of the MSI interrupts in the group as a byte blob.
+    ///
+    /// The blob starts with a header made of the little endian `msi::BLOB_VERSION` (2 bytes)
+    /// and number of interrupts (4 bytes), followed by the configuration of each interrupt
+    /// encoded with `MsiIrqConfig::to_le_bytes()`. It can be restored with `import_blob()`.
+    #[cfg(feature = "msi")]
+    fn export_blob(&self) -> Result<Vec<u8>>
+    where
+        Self::InterruptType: MsiInterrupt,
+    {
+        let len = u32::try_from(self.len()).map_err(|_| Error::InvalidConfiguration)?;
+        let mut blob =
+            Vec::with_capacity(msi::BLOB_HEADER_SIZE + self.len() * msi::CONFIG_BLOB_SIZE);
+        blob.extend_from_slice(&msi::BLOB_VERSION.to_le_bytes());
+        blob.extend_from_slice(&len.to_le_bytes());
+        for index in 0..self.len() {
+            let irq = self.get(index).ok_or(Error::InvalidConfiguration)?;
+            blob.extend_from_slice(&irq.get_config()?.to_le_bytes());
+        }
+        Ok(blob)
+    }
+
+    /// Restore the configuration of the MSI interrupts in the group from a blob created by
+    /// `export_blob()`.
+    ///
+    /// If the group is empty, the interrupts described by the blob are allocated first, and
+    /// released if the import fails. Otherwise, the group must have as many interrupts as the
+    /// blob. Fails with `Error::InvalidConfiguration` for blobs of an unknown version,
+    /// truncated blobs and blobs with invalid configurations. All the configurations are
+    /// checked with `ConfigurableInterrupt::validate()` before any of them is applied, so no
+    /// configuration is changed if one of them is rejected. Interrupts that don't implement
+    /// `validate()` are only checked by `update()`, in which case a configuration rejected
+    /// by `update()` leaves the preceding ones applied.
+    #[cfg(feature = "msi")]
+    fn import_blob(&mut self, blob: &[u8]) -> Result<()>
+    where
+        Self::InterruptType: MsiInterrupt,
+    {
+        if blob.len() < msi::BLOB_HEADER_SIZE
+            || u16::from_le_bytes([blob[0], blob[1]]) != msi::BLOB_VERSION
+        {
+            return Err(Error::InvalidConfiguration);
+        }
+        let len = u32::from_le_bytes([blob[2], blob[3], blob[4], blob[5]]) as usize;
+        let body = &blob[msi::BLOB_HEADER_SIZE..];
+        if len.checked_mul(msi::CONFIG_BLOB_SIZE) != Some(body.len()) {
+            return Err(Error::InvalidConfiguration);
+        }
+        let configs = body
+            .chunks_exact(msi::CONFIG_BLOB_SIZE)
+            .map(|chunk| {
+                let bytes = <&[u8; msi::CONFIG_BLOB_SIZE]>::try_from(chunk)
+                    .map_err(|_| Error::InvalidConfiguration)?;
+                msi::MsiIrqConfig::from_le_bytes(bytes)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let allocate = self.is_empty();
+        if allocate {
+            self.allocate_interrupts(len)?;
+        } else if self.len() != len {
+            return Err(Error::InvalidConfiguration);
+        }
+        let mut result = configs.iter().enumerate().try_for_each(|(index, config)| {
+            let irq = self.get(index).ok_or(Error::InvalidConfiguration)?;
+            match irq.validate(config) {
+                Ok(()) | Err(Error::OperationNotSupported) => Ok(()),
+                Err(e) => Err(e),
+            }
+        });
+        if result.is_ok() {
+            result = configs.iter().enumerate().try_for_each(|(index, config)| {
+                self.get(index)
+                    .ok_or(Error::InvalidConfiguration)?
+                    .update(config)
+            });
+        }
+        if result.is_err() && allocate {
+            let _ = self.free_interrupts();
+        }
+        result
+    }
+
+    /// Return the value of the Table Size field of the MSI-X capability describing the group.
+    ///
+    /// The field encodes the number of entries of the MSI-X table minus one, on 11 bits.
+    #[cfg(feature = "msi")]
+    fn msix_table_size_field(&self) -> u16
+    where
+        Self::InterruptType: MsiInterrupt,
+    {
+        debug_assert!(
+            (1..=msi::MSIX_MAX_VECTORS).contains(&self.len()),
+            "invalid number of MSI-X vectors"
+        );
+        self.len().saturating_sub(1).min(msi::MSIX_MAX_VECTORS - 1) as u16
+    }
+
+    /// Return the location of the MSI-X table and PBA set by `set_msix_layout()`.
+    #[cfg(feature = "msi")]
+    fn msix_layout(&self) -> Option<MsixLayout>
+    where
+        Self::InterruptType: MsiInterrupt,
+    {
+        None
+    }
+
+    /// Update only the message data of the index-th MSI interrupt in the group.
+    ///
+    /// Backends that can reprogram the data of a route more cheaply than a whole route should
+    /// override this. The default implementation updates the whole configuration.
+    #[cfg(feature = "msi")]
+    fn update_data(&self, index: usize, data: u32) -> Result<()>
+    where
+        Self::InterruptType: MsiInterrupt,
+    {
+        let irq = self.get(index).ok_or(Error::InvalidConfiguration)?;
+        let mut config = irq.get_config()?;
+        config.data = data;
+        irq.update(&config)
+    }
+
+    /// Update only the message address of the index-th MSI interrupt in the group.
+    ///
+    /// Backends that can reprogram the address of a route more cheaply than a whole route
+    /// should override this. The default implementation updates the whole configuration.
+    #[cfg(feature = "msi")]
+    fn update_address(&self, index: usize, low_addr: u32, high_addr: u32) -> Result<()>
+    where
+        Self::InterruptType: MsiInterrupt,
+    {
+        let irq = self.get(index).ok_or(Error::InvalidConfiguration)?;
+        let mut config = irq.get_config()?;
+        config.low_addr = low_addr;
+        config.high_addr = high_addr;
+        irq.update(&config)
+    }
+
+    /// Emulate a guest write of `data` at `offset` of the MSI-X table of the group.
+    ///
+    /// The write is decoded according to the standard layout of the MSI-X table, where the
+    /// index-th interrupt of the group is described by the entry at `index * 16`. Writes to the
+    /// message address and data update the configuration of the interrupt, and writes to the
+    /// vector control mask or unmask it. Fails with `Error::InvalidConfiguration` for accesses
+    /// that are not naturally aligned DWORD or QWORD accesses, or beyond the end of the table.
+    #[cfg(feature = "msi")]
+    fn handle_table_write(&self, offset: u64, data: &[u8]) -> Result<()>
+    where
+        Self::InterruptType: MsiInterrupt,
+    {
+        let (index, field) = msi::msix_table_access(offset, data.len())?;
+        let irq = self.get(index).ok_or(Error::InvalidConfiguration)?;
+        for (field, dword) in (field..).step_by(4).zip(data.chunks_exact(4)) {
+            let value = u32::from_le_bytes([dword[0], dword[1], dword[2], dword[3]]);
+            match field {
+                msi::MSIX_MSG_ADDR_LO => {
+                    self.update_address(index, value, irq.get_config()?.high_addr)?
+                }
+                msi::MSIX_MSG_ADDR_HI => {
+                    self.update_address(index, irq.get_config()?.low_addr, value)?
+                }
+                msi::MSIX_MSG_DATA => self.update_data(index, value)?,
+                _ if value & msi::MSIX_VECTOR_CTRL_MASK_BIT != 0 => {
+                    irq.mask()?;
+                }
+                _ => {
+                    irq.unmask()?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Emulate a guest read of `data.len()` bytes at `offset` of the MSI-X table of the group.
+    ///
+    /// This is the counterpart of `handle_table_write()`, with the same constraints on the
+    /// accesses.
+    #[cfg(feature = "msi")]
+    fn handle_table_read(&self, offset: u64, data: &mut [u8]) -> Result<()>
+    where
+        Self::InterruptType: MsiInterrupt,
+    {
+        let (index, field) = msi::msix_table_access(offset, data.len())?;
+        let irq = self.get(index).ok_or(Error::InvalidConfiguration)?;
+        let config = irq.get_config()?;
+        for (field, dword) in (field..).step_by(4).zip(data.chunks_exact_mut(4)) {
+            let value = match field {
+                msi::MSIX_MSG_ADDR_LO => config.low_addr,
+                msi::MSIX_MSG_ADDR_HI => config.high_addr,
+                msi::MSIX_MSG_DATA => config.data,
+                _ => u32::from(irq.is_masked()?) * msi::MSIX_VECTOR_CTRL_MASK_BIT,
+            };
+            dword.copy_from_slice(&value.to_le_bytes());
+        }
+        Ok(())
+    }
+
+    /// Return the destination APIC ID of the index-th MSI interrupt in the group.
+    ///
+    /// Implementations holding a cached configuration should override this to avoid reading
+    /// the whole configuration.
+    #[cfg(feature = "msi")]
+    fn target_cpu(&self, index: usize) -> Result<u32>
+    where
+        Self::InterruptType: MsiInterrupt,
+    {
+        let irq = self.get(index).ok_or(Error::InvalidConfiguration)?;
+        Ok(irq.get_config()?.target_cpu())
+    }
+
+    /// Return the indices of the MSI interrupts in the group whose destination is the vCPU
+    /// with the APIC ID `cpu`.
+    ///
+    /// This allows moving all the interrupts of a vCPU that goes offline to other vCPUs.
+    #[cfg(feature = "msi")]
+    fn sources_targeting(&self, cpu: u32) -> Result<Vec<usize>>
+    where
+        Self::InterruptType: MsiInterrupt,
+    {
+        let mut indices = Vec::new();
+        for index in 0..self.len() {
+            if self.target_cpu(index)? == cpu {
+                indices.push(index);
+            }
+        }
+        Ok(indices)
+    }
+
+    /// Move the MSI interrupts in the group whose destination is the vCPU with the APIC ID
+    /// `cpu` to the vCPU `fallback`, and return their indices.
+    ///
+    /// This must be called before a vCPU goes offline. Each interrupt is masked while its
+    /// address is updated with `update_address()`, so that an interrupt triggered meanwhile is
+    /// kept pending and delivered to `fallback` when it is unmasked. Interrupts that were
+    /// already masked stay masked.
+    #[cfg(feature = "msi")]
+    fn evacuate_cpu(&self, cpu: u32, fallback: u32) -> Result<Vec<usize>>
+    where
+        Self::InterruptType: MsiInterrupt,
+    {
+        let indices = self.sources_targeting(cpu)?;
+        for &index in &indices {
+            let irq = self.get(index).ok_or(Error::InvalidConfiguration)?;
+            let mut config = irq.get_config()?;
+            config.set_target_cpu(fallback)?;
+            let masked = irq.mask()?;
+            let result = self.update_address(index, config.low_addr, config.high_addr);
+            if masked {
+                irq.unmask()?;
+            }
+            result?;
+        }
+        Ok(indices)
+    }
+
+    /// Return the vector of the index-th MSI interrupt in the group.
+    #[cfg(feature = "msi")]
+    fn vector(&self, index: usize) -> Result<u8>
+    where
+        Self::InterruptType: MsiInterrupt,
+    {
+        let irq = self.get(index).ok_or(Error::InvalidConfiguration)?;
+        Ok(irq.get_config()?.vector())
+    }
+
+    /// Return the delivery mode of the index-th MSI interrupt in the group.
+    #[cfg(feature = "msi")]
+    fn delivery_mode(&self, index: usize) -> Result<u8>
+    where
+        Self::InterruptType: MsiInterrupt,
+    {
+        let irq = self.get(index).ok_or(Error::InvalidConfiguration)?;
+        Ok(irq.get_config()?.delivery_mode())
+    }
+
+    /// Return the trigger modes used by the MSI interrupts in the group.
+    ///
+    /// The trigger mode is part of the configuration of each interrupt, so a group can mix
+    /// edge and level triggered interrupts, e.g. for a controller with a level triggered error
+    /// interrupt and edge triggered data interrupts.
+    #[cfg(feature = "msi")]
+    fn trigger_modes(&self) -> Result<TriggerModes>
+    where
+        Self::InterruptType: MsiInterrupt,
+    {
+        let mut modes = TriggerModes::default();
+        for index in 0..self.len() {
+            let irq = self.get(index).ok_or(Error::InvalidConfiguration)?;
+            match irq.get_config()?.trigger_mode {
+                TriggerMode::Edge => modes.edge = true,
+                TriggerMode::Level => modes.level = true,
+            }
+        }
+        Ok(modes)
+    }
+
+    /// Deassert the index-th MSI interrupt in the group if it is level triggered.
+    ///
+    /// This is a no-op for edge triggered interrupts, so that devices with both edge and level
+    /// triggered interrupts can deassert any of them without checking its trigger mode.
+    #[cfg(feature = "msi")]
+    fn deassert(&self, index: usize) -> Result<()>
+    where
+        Self::InterruptType: MsiInterrupt + LevelInterrupt,
+    {
+        let irq = self.get(index).ok_or(Error::InvalidConfiguration)?;
+        match irq.get_config()?.trigger_mode {
+            TriggerMode::Edge => Ok(()),
+            TriggerMode::Level => irq.clear(),
+        }
+    }
+
+    /// Start recording the latency between triggering the interrupts in the group and their
+    /// acknowledgement by the guest.
+    ///
+    /// Implementations should not add overhead to the trigger path while tracking is disabled.
+    fn enable_latency_tracking(&self) -> Result<()> {
+        Err(Error::OperationNotSupported)
+    }
+
+    /// Return the latency statistics recorded since latency tracking was enabled.
+    fn latency_stats(&self) -> Result<LatencyStats> {
+        Err(Error::OperationNotSupported)
+    }
+
+    /// Return the stride between the doorbells of consecutive interrupts in the doorbell
+    /// region of the group, or `None` if the group doesn't expose a doorbell region.
+    fn doorbell_stride(&self) -> Option<u64> {
+        None
+    }
+
+    /// Handle a write to the doorbell region of the group.
+    ///
+    /// A write at `offset` triggers the interrupt with index `offset / doorbell_stride()`.
+    /// The written value is ignored. Offsets that are not aligned to the stride or are out of
+    /// bounds are rejected with `Error::InvalidConfiguration`.
+    fn doorbell_write(&self, offset: u64, _value: u64) -> Result<()>
+    where
+        Self::InterruptType: EdgeInterrupt,
+    {
+        let stride = self.doorbell_stride().ok_or(Error::OperationNotSupported)?;
+        if offset.checked_rem(stride) != Some(0) {
+            return Err(Error::InvalidConfiguration);
+        }
+        let index = usize::try_from(offset / stride).map_err(|_| Error::InvalidConfiguration)?;
+        self.get(index)
+            .ok_or(Error::InvalidConfiguration)?
+            .trigger()
+    }
+
+    /// Return the number of pending bits the group can track.
+    ///
+    /// This is the number of bits a device must expose in the MSI-X Pending Bit Array (PBA)
+    /// of the group. The default implementation tracks a pending bit for every interrupt in
+    /// the group.
+    #[cfg(feature = "maskable")]
+    fn pending_bit_capacity(&self) -> usize
+    where
+        Self::InterruptType: MaskableInterrupt,
+    {
+        self.len()
+    }
+
+    /// Trigger all the interrupts in the group.
+    ///
+    /// Each interrupt handles the trigger according to its own state, so disabled interrupts
+    /// discard it and masked interrupts record it as pending. All the interrupts are triggered
+    /// even if some of them fail. The errors encountered are returned as an `Error::Multiple`.
+    fn trigger_all(&self) -> Result<()>
+    where
+        Self::InterruptType: EdgeInterrupt,
+    {
+        collect_errors((0..self.len()).map(|index| {
+            self.get(index)
+                .ok_or(Error::InvalidConfiguration)?
+                .trigger()
+        }))
+    }
+
+    /// Replay on the destination of a live migration the interrupts `fired` on the source,
+    /// as returned by `take_fired_since_freeze()`, according to `policy`.
+    ///
+    /// The default implementation only supports `ReplayPolicy::ReplayPending`; groups that can
+    /// discard a trigger of an interrupt should override it to support
+    /// `ReplayPolicy::SuppressOneShot`, as `sparse::SparseInterruptSourceGroup` does with
+    /// `guard::GuardedInterrupt::suppress_next_trigger()`.
+    fn replay_fired(&self, fired: &[usize], policy: ReplayPolicy) -> Result<()>
+    where
+        Self::InterruptType: EdgeInterrupt,
+    {
+        if policy != ReplayPolicy::ReplayPending {
+            return Err(Error::OperationNotSupported);
+        }
+        collect_errors(fired.iter().map(|&index| {
+            self.get(index)
+                .ok_or(Error::InvalidConfiguration)?
+                .trigger()
+        }))
+    }
+
+    /// Return an empty `TriggerBatch` for triggering interrupts of the group once per batch
+    /// of events.
+    fn trigger_batch_builder(&self) -> TriggerBatch<'_, Self>
+    where
+        Self: Sized,
+    {
+        TriggerBatch::new(self)
+    }
+
+    /// Mask all the interrupts in the group and return their previous mask state.
+    ///
+    /// Masked interrupts are remembered but not delivered, so the configuration of the
+    /// interrupts can be safely updated until `restore_mask()` is called with the returned
+    /// snapshot. If masking fails for an interrupt, the interrupts preceding it remain masked.
+    #[cfg(feature = "maskable")]
+    fn mask_all_and_snapshot(&self) -> Result<MaskSnapshot>
+    where
+        Self::InterruptType: MaskableInterrupt,
+    {
+        let mut masked = Vec::with_capacity(self.len());
+        for index in 0..self.len() {
+            let irq = self.get(index).ok_or(Error::InvalidConfiguration)?;
+            // `mask()` reports whether the interrupt was unmasked before the call.
+            masked.push(!irq.mask()?);
+        }
+        Ok(MaskSnapshot { masked })
+    }
+
+    /// Restore the mask state of the interrupts in the group from a snapshot returned by
+    /// `mask_all_and_snapshot()`.
+    ///
+    /// Interrupts that were not masked when the snapshot was taken are unmasked, delivering
+    /// any interrupt that became pending in the meantime.
+    #[cfg(feature = "maskable")]
+    fn restore_mask(&self, snapshot: MaskSnapshot) -> Result<()>
+    where
+        Self::InterruptType: MaskableInterrupt,
+    {
+        if snapshot.masked.len() != self.len() {
+            return Err(Error::InvalidConfiguration);
+        }
+        for (index, masked) in snapshot.masked.into_iter().enumerate() {
+            if !masked {
+                self.get(index)
+                    .ok_or(Error::InvalidConfiguration)?
+                    .unmask()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Enable all the interrupt source groups in `groups`.
+///
+/// All the groups are enabled even if some of them fail. The errors encountered are
+/// returned as an `Error::Multiple`.
+pub fn enable_all<G: InterruptSourceGroup>(groups: &[&G]) -> Result<()> {
+    collect_errors(groups.iter().map(|group| group.enable()))
+}
+
+/// Disable all the interrupt source groups in `groups`.
+///
+/// All the groups are disabled even if some of them fail. The errors encountered are
+/// returned as an `Error::Multiple`.
+pub fn disable_all<G: InterruptSourceGroup>(groups: &[&G]) -> Result<()> {
+    collect_errors(groups.iter().map(|group| group.disable()))
+}
+
+// Invoke a user supplied callback, returning `None` if it panicked and panics are caught.
+pub(crate) fn call_callback<R>(f: impl FnOnce() -> R) -> Option<R> {
+    #[cfg(feature = "panic-safe-callbacks")]
+    return std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)).ok();
+    #[cfg(not(feature = "panic-safe-callbacks"))]
+    return Some(f());
+}
+
+fn collect_errors<I: Iterator<Item = Result<()>>>(results: I) -> Result<()> {
+    let errors: Vec<Error> = results.filter_map(|result| result.err()).collect();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Multiple(errors))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    pub(crate) struct DummyInterrupt {
+        id: InterruptId,
+        pub(crate) triggered: AtomicUsize,
+        enabled: Mutex<bool>,
+        #[cfg(feature = "maskable")]
+        masked: Mutex<bool>,
+        #[cfg(feature = "configurable")]
+        config: Mutex<u32>,
+    }
+
+    impl Interrupt for DummyInterrupt {
+        fn kind(&self) -> InterruptSourceKind {
+            InterruptSourceKind::MsiX
+        }
+
+        fn id(&self) -> InterruptId {
+            self.id
+        }
+
+        fn enable(&self) -> Result<bool> {
+            let mut enabled = self.enabled.lock().unwrap();
+            Ok(!std::mem::replace(&mut *enabled, true))
+        }
+
+        fn disable(&self) -> Result<bool> {
+            let mut enabled = self.enabled.lock().unwrap();
+            Ok(std::mem::replace(&mut *enabled, false))
+        }
+
+        fn is_enabled(&self) -> Result<bool> {
+            Ok(*self.enabled.lock().unwrap())
+        }
+    }
+
+    impl EdgeInterrupt for DummyInterrupt {
+        fn trigger(&self) -> Result<()> {
+            self.triggered.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "configurable")]
+    impl ConfigurableInterrupt for DummyInterrupt {
+        type Cfg = u32;
+
+        fn update(&self, config: &u32) -> Result<()> {
+            *self.config.lock().unwrap() = *config;
+            Ok(())
+        }
+
+        fn get_config(&self) -> Result<u32> {
+            Ok(*self.config.lock().unwrap())
+        }
+    }
+
+    #[cfg(feature = "maskable")]
+    impl MaskableInterrupt for DummyInterrupt {
+        fn mask(&self) -> Result<bool> {
+            let mut masked = self.masked.lock().unwrap();
+            Ok(!std::mem::replace(&mut *masked, true))
+        }
+
+        fn unmask(&self) -> Result<Unmasked> {
+            *self.masked.lock().unwrap() = false;
+            Ok(Unmasked::default())
+        }
+
+        fn is_masked(&self) -> Result<bool> {
+            Ok(*self.masked.lock().unwrap())
+        }
+    }
+
+    #[derive(Default)]
+    pub(crate) struct DummyGroup {
+        irqs: Vec<Arc<DummyInterrupt>>,
+        limit: Option<usize>,
+        enabled: AtomicBool,
+    }
+
+    impl InterruptSourceGroup for DummyGroup {
+        type InterruptType = DummyInterrupt;
+        type InterruptWrapper = Arc<DummyInterrupt>;
+
+        fn len(&self) -> usize {
+            self.irqs.len()
+        }
+
+        fn enable(&self) -> Result<()> {
+            self.enabled.store(true, Ordering::SeqCst);
+            Ok(())
+        }
+
+        fn disable(&self) -> Result<()> {
+            self.enabled.store(false, Ordering::SeqCst);
+            Ok(())
+        }
+
+        fn is_group_enabled(&self) -> Result<bool> {
+            Ok(self.enabled.load(Ordering::SeqCst))
+        }
+
+        fn get(&self, index: usize) -> Option<Self::InterruptWrapper> {
+            self.irqs.get(index).cloned()
+        }
+
+        fn with<R>(&self, index: usize, f: impl FnOnce(&DummyInterrupt) -> R) -> Option<R> {
+            self.irqs.get(index).map(|irq| f(irq))
+        }
+
+        fn allocate_interrupts(&mut self, size: usize) -> Result<()> {
+            if matches!(self.limit, Some(limit) if self.irqs.len() + size > limit) {
+                return Err(Error::InterruptAllocationError);
+            }
+            self.irqs
+                .extend((0..size).map(|_| Arc::new(DummyInterrupt::default())));
+            Ok(())
+        }
+
+        fn free_interrupts(&mut self) -> Result<()> {
+            #[cfg(feature = "maskable")]
+            self.prepare_free()?;
+            while self.irqs.pop().is_some() {}
+            Ok(())
+        }
+
+        fn doorbell_stride(&self) -> Option<u64> {
+            Some(4)
+        }
+    }
+
+    struct FailingGroup;
+
+    impl InterruptSourceGroup for FailingGroup {
+        type InterruptType = DummyInterrupt;
+        type InterruptWrapper = Arc<DummyInterrupt>;
+
+        fn len(&self) -> usize {
+            0
+        }
+
+        fn enable(&self) -> Result<()> {
+            Err(Error::InterruptNotChanged)
+        }
+
+        fn disable(&self) -> Result<()> {
+            Err(Error::InterruptNotChanged)
+        }
+
+        fn get(&self, _index: usize) -> Option<Self::InterruptWrapper> {
+            None
+        }
+
+        fn allocate_interrupts(&mut self, _size: usize) -> Result<()> {
+            Err(Error::InterruptAllocationError)
+        }
+
+        fn free_interrupts(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_enable_disable() {
+        let mut group = DummyGroup::default();
+        group.allocate_interrupts(1).unwrap();
+        let irq = group.get(0).unwrap();
+        assert_eq!(irq.kind(), InterruptSourceKind::MsiX);
+
+        assert!(!irq.is_enabled().unwrap());
+        assert!(irq.enable().unwrap());
+        assert!(!irq.enable().unwrap());
+        assert!(irq.is_enabled().unwrap());
+        assert!(irq.disable().unwrap());
+        assert!(!irq.disable().unwrap());
+    }
+
+    #[test]
+    fn test_interrupt_id() {
+        let mut group = DummyGroup::default();
+        group.allocate_interrupts(2).unwrap();
+        let irq = group.get(0).unwrap();
+        assert_eq!(irq.id(), group.get(0).unwrap().id());
+        assert_ne!(irq.id(), group.get(1).unwrap().id());
+        assert_ne!(InterruptId::next(), InterruptId::next());
+    }
+
+    #[test]
+    #[cfg(feature = "maskable")]
+    fn test_mask_all_and_restore() {
+        let mut group = DummyGroup::default();
+        group.allocate_interrupts(3).unwrap();
+        assert!(group.get(1).unwrap().mask().unwrap());
+        assert!(!group.get(1).unwrap().mask().unwrap());
+
+        let snapshot = group.mask_all_and_snapshot().unwrap();
+        assert_eq!(snapshot.is_masked(0), Some(false));
+        assert_eq!(snapshot.is_masked(1), Some(true));
+        assert_eq!(snapshot.is_masked(3), None);
+        for index in 0..group.len() {
+            assert!(group.get(index).unwrap().is_masked().unwrap());
+        }
+
+        group.restore_mask(snapshot.clone()).unwrap();
+        assert!(!group.get(0).unwrap().is_masked().unwrap());
+        assert!(group.get(1).unwrap().is_masked().unwrap());
+        assert!(!group.get(2).unwrap().is_masked().unwrap());
+
+        group.allocate_interrupts(1).unwrap();
+        assert!(group.restore_mask(snapshot).is_err());
+    }
+
+    #[test]
+    #[cfg(all(feature = "configurable", feature = "maskable"))]
+    fn test_clone_config_into() {
+        let mut src = DummyGroup::default();
+        src.allocate_interrupts(2).unwrap();
+        let mut dst = DummyGroup::default();
+        dst.allocate_interrupts(1).unwrap();
+
+        let irq = src.get(1).unwrap();
+        irq.update(&0x1234).unwrap();
+        assert!(irq.enable().unwrap());
+        assert!(!irq.enable().unwrap());
+        dst.get(0).unwrap().mask().unwrap();
+
+        src.clone_config_into(1, &dst, 0).unwrap();
+        let irq = dst.get(0).unwrap();
+        assert_eq!(irq.get_config().unwrap(), 0x1234);
+        assert!(irq.is_enabled().unwrap());
+        assert!(!irq.is_masked().unwrap());
+
+        assert!(src.clone_config_into(2, &dst, 0).is_err());
+        assert!(src.clone_config_into(0, &dst, 1).is_err());
+    }
+
+    #[test]
+    #[cfg(all(feature = "configurable", feature = "maskable"))]
+    fn test_clone_into_backend() {
+        let mut src = DummyGroup::default();
+        src.allocate_interrupts(3).unwrap();
+        src.get(0).unwrap().update(&0x10).unwrap();
+        src.get(1).unwrap().enable().unwrap();
+        src.get(2).unwrap().mask().unwrap();
+
+        let mut dst = DummyGroup::default();
+        src.clone_into_backend(&mut dst).unwrap();
+        assert_eq!(dst.len(), 3);
+        let configs: Vec<_> = dst
+            .snapshot_configs()
+            .unwrap()
+            .iter()
+            .map(|snapshot| snapshot.config)
+            .collect();
+        assert_eq!(configs, vec![0x10, 0, 0]);
+        assert!(dst.get(1).unwrap().is_enabled().unwrap());
+        assert!(!dst.get(1).unwrap().is_masked().unwrap());
+        assert!(dst.get(2).unwrap().is_masked().unwrap());
+
+        // The destination must be empty.
+        assert!(src.clone_into_backend(&mut dst).is_err());
+        assert_eq!(dst.len(), 3);
+
+        // The destination backend may not have room for the interrupts.
+        let mut dst = DummyGroup {
+            limit: Some(2),
+            ..Default::default()
+        };
+        assert!(src.clone_into_backend(&mut dst).is_err());
+        assert!(dst.is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "configurable")]
+    fn test_snapshot_configs() {
+        let mut group = DummyGroup::default();
+        assert!(group.snapshot_configs().unwrap().is_empty());
+        group.allocate_interrupts(2).unwrap();
+        group.get(1).unwrap().update(&0x1234).unwrap();
+        group.enable().unwrap();
+        group.get(0).unwrap().enable().unwrap();
+        assert_eq!(
+            group.snapshot_configs().unwrap(),
+            vec![
+                ConfigSnapshot {
+                    config: 0,
+                    enabled: true
+                },
+                ConfigSnapshot {
+                    config: 0x1234,
+                    enabled: false
+                }
+            ]
+        );
+        assert_eq!(
+            group.get(1).unwrap().get_effective_config().unwrap(),
+            0x1234
+        );
+    }
+
+    #[test]
+    fn test_effective_enabled() {
+        let mut group = DummyGroup::default();
+        group.allocate_interrupts(2).unwrap();
+        group.get(0).unwrap().enable().unwrap();
+        assert!(!group.effective_enabled(0));
+
+        group.enable().unwrap();
+        assert!(group.effective_enabled(0));
+        assert!(!group.effective_enabled(1));
+        assert!(!group.effective_enabled(2));
+
+        // Disabling the group doesn't change the enable state of the interrupts.
+        group.disable().unwrap();
+        assert!(!group.effective_enabled(0));
+        assert!(group.get(0).unwrap().is_enabled().unwrap());
+
+        // Groups without an enable state of their own only depend on their interrupts.
+        let group = fixed::StaticInterruptGroup::new([DummyInterrupt::default()]);
+        assert!(!group.effective_enabled(0));
+        group.enable().unwrap();
+        assert!(group.effective_enabled(0));
+    }
+
+    #[test]
+    fn test_enable_disable_all() {
+        let group = DummyGroup::default();
+        assert!(enable_all(&[&group, &group]).is_ok());
+        assert!(disable_all(&[&group]).is_ok());
+        assert!(enable_all::<DummyGroup>(&[]).is_ok());
+
+        match disable_all(&[&FailingGroup, &FailingGroup]) {
+            Err(Error::Multiple(errors)) => assert_eq!(errors.len(), 2),
+            _ => panic!("expected multiple errors"),
+        }
+        let err = enable_all(&[&FailingGroup]).unwrap_err();
+        assert_eq!(
+            format!("{}", err),
+            "Interrupt error: multiple errors occured: \
+             [Interrupt error: the interrupt state could not be changed]"
+        );
+    }
+
+    #[test]
+    fn test_errno() {
+        assert_eq!(Error::OperationNotSupported.errno(), 95);
+        assert_eq!(Error::InvalidConfiguration.errno(), 22);
+        assert_eq!(Error::InterruptNotTriggered.errno(), 5);
+        assert_eq!(Error::InterruptAllocationError.errno(), 28);
+        assert_eq!(Error::DeliveryOverflow.errno(), 11);
+        assert_eq!(Error::NotQuiescent(0).errno(), 16);
+        assert_eq!(
+            Error::InvalidStateTransition {
+                from: InterruptState::Unallocated,
+                to: InterruptState::Armed,
+            }
+            .errno(),
+            1
+        );
+        assert_eq!(
+            Error::Multiple(vec![Error::InvalidConfiguration, Error::InterruptFreeError]).errno(),
+            22
+        );
+        assert_eq!(Error::Multiple(Vec::new()).errno(), 5);
+    }
+
+    #[test]
+    fn test_latency_stats() {
+        assert_eq!(LatencyStats::from_samples(&mut []), LatencyStats::default());
+
+        let mut samples: Vec<Duration> = (1..=100).rev().map(Duration::from_micros).collect();
+        let stats = LatencyStats::from_samples(&mut samples);
+        assert_eq!(stats.samples, 100);
+        assert_eq!(stats.min, Duration::from_micros(1));
+        assert_eq!(stats.p50, Duration::from_micros(50));
+        assert_eq!(stats.p90, Duration::from_micros(90));
+        assert_eq!(stats.p99, Duration::from_micros(99));
+        assert_eq!(stats.max, Duration::from_micros(100));
+    }
+
+    #[test]
+    fn test_doorbell_write() {
+        let mut group = DummyGroup::default();
+        group.allocate_interrupts(2).unwrap();
+
+        group.doorbell_write(4, 0).unwrap();
+        assert_eq!(group.get(0).unwrap().triggered.load(Ordering::SeqCst), 0);
+        assert_eq!(group.get(1).unwrap().triggered.load(Ordering::SeqCst), 1);
+
+        assert!(group.doorbell_write(2, 0).is_err());
+        assert!(group.doorbell_write(8, 0).is_err());
+        assert!(group.doorbell_write(u64::MAX - 3, 0).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "maskable")]
+    fn test_pending_bit_capacity() {
+        let mut group = DummyGroup::default();
+        assert_eq!(group.pending_bit_capacity(), 0);
+        group.allocate_interrupts(5).unwrap();
+        assert_eq!(group.pending_bit_capacity(), 5);
+    }
+
+    #[test]
+    fn test_trigger_and_wait() {
+        let irq = DummyInterrupt::default();
+        assert!(matches!(
+            irq.trigger_and_wait(Duration::from_millis(1)),
+            Err(Error::OperationNotSupported)
+        ));
+        assert_eq!(irq.triggered.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_trigger_all() {
+        let mut group = DummyGroup::default();
+        group.trigger_all().unwrap();
+        group.allocate_interrupts(3).unwrap();
+        group.trigger_all().unwrap();
+        for index in 0..group.len() {
+            assert_eq!(
+                group.get(index).unwrap().triggered.load(Ordering::SeqCst),
+                1
+            );
+        }
+    }
+
+    #[test]
+    fn test_replay_fired() {
+        let mut group = DummyGroup::default();
+        group.allocate_interrupts(3).unwrap();
+        group
+            .replay_fired(&[2, 0, 2], ReplayPolicy::ReplayPending)
+            .unwrap();
+        let triggered: Vec<_> = (0..3)
+            .map(|index| group.get(index).unwrap().triggered.load(Ordering::SeqCst))
+            .collect();
+        assert_eq!(triggered, vec![1, 0, 2]);
+
+        assert!(group
+            .replay_fired(&[3], ReplayPolicy::ReplayPending)
+            .is_err());
+        // The default implementation can't discard triggers (see the sparse group tests).
+        assert!(matches!(
+            group.replay_fired(&[0], ReplayPolicy::SuppressOneShot),
+            Err(Error::OperationNotSupported)
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "maskable")]
+    fn test_prepare_free() {
+        type Log = Arc<Mutex<Vec<(&'static str, usize)>>>;
+
+        /// Interrupt logging the teardown operations in a log shared with its group.
+        struct RecordingInterrupt {
+            id: InterruptId,
+            index: usize,
+            log: Log,
+        }
+
+        impl Interrupt for RecordingInterrupt {
+            fn kind(&self) -> InterruptSourceKind {
+                InterruptSourceKind::MsiX
+            }
+
+            fn id(&self) -> InterruptId {
+                self.id
+            }
+
+            fn disable(&self) -> Result<bool> {
+                self.log.lock().unwrap().push(("disable", self.index));
+                Ok(true)
+            }
+        }
+
+        impl MaskableInterrupt for RecordingInterrupt {
+            fn mask(&self) -> Result<bool> {
+                Ok(true)
+            }
+
+            fn unmask(&self) -> Result<Unmasked> {
+                self.log.lock().unwrap().push(("unmask", self.index));
+                Ok(Unmasked::default())
+            }
+        }
+
+        /// Group following the teardown order documented by `free_interrupts()`.
+        #[derive(Default)]
+        struct RecordingGroup {
+            irqs: Vec<Arc<RecordingInterrupt>>,
+            log: Log,
+        }
+
+        impl InterruptSourceGroup for RecordingGroup {
+            type InterruptType = RecordingInterrupt;
+            type InterruptWrapper = Arc<RecordingInterrupt>;
+
+            fn len(&self) -> usize {
+                self.irqs.len()
+            }
+
+            fn enable(&self) -> Result<()> {
+                Ok(())
+            }
+
+            fn disable(&self) -> Result<()> {
+                Ok(())
+            }
+
+            fn get(&self, index: usize) -> Option<Self::InterruptWrapper> {
+                self.irqs.get(index).cloned()
+            }
+
+            fn allocate_interrupts(&mut self, size: usize) -> Result<()> {
+                let start = self.irqs.len();
+                for index in start..start + size {
+                    self.irqs.push(Arc::new(RecordingInterrupt {
+                        id: InterruptId::next(),
+                        index,
+                        log: self.log.clone(),
+                    }));
+                }
+                Ok(())
+            }
+
+            fn free_interrupts(&mut self) -> Result<()> {
+                self.prepare_free()?;
+                while let Some(irq) = self.irqs.pop() {
+                    self.log.lock().unwrap().push(("unregister", irq.index));
+                }
+                Ok(())
+            }
+        }
+
+        let mut group = RecordingGroup::default();
+        group.allocate_interrupts(2).unwrap();
+        group.free_interrupts().unwrap();
+        assert!(group.is_empty());
+        assert_eq!(
+            *group.log.lock().unwrap(),
+            vec![
+                ("disable", 0),
+                ("disable", 1),
+                ("unmask", 0),
+                ("unmask", 1),
+                ("unregister", 1),
+                ("unregister", 0),
+            ]
+        );
+
+        // Interrupts are disabled before being unmasked, so pending interrupts are discarded.
+        let mut group = DummyGroup::default();
+        group.allocate_interrupts(2).unwrap();
+        let irqs: Vec<_> = (0..2).map(|index| group.get(index).unwrap()).collect();
+        for irq in irqs.iter() {
+            irq.enable().unwrap();
+            irq.mask().unwrap();
+        }
+        group.free_interrupts().unwrap();
+        for irq in irqs.iter() {
+            assert!(!irq.is_enabled().unwrap());
+            assert!(!irq.is_masked().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_assert_quiescent() {
+        let mut group = DummyGroup::default();
+        group.allocate_interrupts(2).unwrap();
+        group.assert_quiescent().unwrap();
+
+        group.get(1).unwrap().enable().unwrap();
+        if cfg!(debug_assertions) {
+            let err = group.assert_quiescent().unwrap_err();
+            assert!(matches!(err, Error::NotQuiescent(1)));
+            assert_eq!(
+                format!("{}", err),
+                "Interrupt error: the interrupt 1 is not quiescent"
+            );
+        }
+        group.get(1).unwrap().disable().unwrap();
+        group.assert_quiescent().unwrap();
+    }
+
+    #[test]
+    fn test_allocate_interrupts_best_effort() {
+        let mut group = DummyGroup {
+            limit: Some(12),
+            ..Default::default()
+        };
+        assert!(group.allocate_interrupts(16).is_err());
+        assert_eq!(group.allocate_interrupts_best_effort(16).unwrap(), 12);
+        assert_eq!(group.len(), 12);
+        assert!(group.allocate_interrupts_best_effort(1).is_err());
+        assert!(group.allocate_interrupts_best_effort(0).is_err());
+    }
 }
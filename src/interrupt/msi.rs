@@ -8,7 +8,56 @@
 //! MSI interrupts are typically used by PCI devices.
 //! These structs and traits can be used to configure both MSI and MSIX interrupts.
 
-use crate::interrupt::{ConfigurableInterrupt, MaskableInterrupt};
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+#[cfg(feature = "std")]
+use std::sync::{Mutex, RwLock};
+
+#[cfg(feature = "std")]
+use crate::interrupt::call_callback;
+use crate::interrupt::{ConfigSnapshot, ConfigurableInterrupt, Error, MaskableInterrupt, Result};
+#[cfg(feature = "std")]
+use crate::interrupt::{EdgeInterrupt, Interrupt, InterruptId, InterruptSourceKind, Unmasked};
+
+/// Trigger mode of MSI/MSI-X interrupts.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum TriggerMode {
+    /// The message is sent once per interrupt event.
+    Edge,
+    /// The message is re-sent while the interrupt condition persists.
+    ///
+    /// Level triggered MSI interrupts are resampled like legacy level interrupts and should
+    /// implement `AutoRetriggerInterrupt` so that they are retriggered if the device still
+    /// requires service.
+    Level,
+}
+
+// Deriving `Default` for enums requires a newer toolchain.
+#[allow(clippy::derivable_impls)]
+impl Default for TriggerMode {
+    fn default() -> Self {
+        TriggerMode::Edge
+    }
+}
+
+/// Trigger modes used by the interrupts of a group.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct TriggerModes {
+    /// At least one interrupt is edge triggered.
+    pub edge: bool,
+    /// At least one interrupt is level triggered.
+    pub level: bool,
+}
+
+impl TriggerModes {
+    /// Return whether the group has both edge and level triggered interrupts.
+    pub fn is_mixed(&self) -> bool {
+        self.edge && self.level
+    }
+}
 
 /// Configuration data for MSI/MSI-X interrupts.
 ///
@@ -22,7 +71,810 @@ pub struct MsiIrqConfig {
     /// Data to write to delivery message signaled interrupt.
     pub data: u32,
     /// Unique ID of the device to delivery message signaled interrupt.
+    ///
+    /// Used by the GIC ITS on ARM and by interrupt remapping; ignored by other backends.
     pub devid: u32,
+    /// Trigger mode of the message signaled interrupt.
+    pub trigger_mode: TriggerMode,
+}
+
+/// Base of the x86 interrupt address region that MSI addresses must fall in.
+pub const MSI_ADDR_REGION_BASE: u32 = 0xfee0_0000;
+
+/// Mask of the bits of an MSI address that identify the x86 interrupt address region.
+pub const MSI_ADDR_REGION_MASK: u32 = 0xfff0_0000;
+
+/// Maximum number of vectors a multiple message MSI capability can enable.
+pub const MSI_MAX_VECTORS: usize = 32;
+
+/// Maximum number of entries of an MSI-X table.
+pub const MSIX_MAX_VECTORS: usize = 2048;
+
+/// Version of the format of the blobs exported by `InterruptSourceGroup::export_blob()`.
+pub const BLOB_VERSION: u16 = 1;
+
+/// Size of the header of the blobs exported by `InterruptSourceGroup::export_blob()`: the
+/// format version (2 bytes) and the number of interrupts (4 bytes).
+pub const BLOB_HEADER_SIZE: usize = 6;
+
+/// Size of an `MsiIrqConfig` encoded with `MsiIrqConfig::to_le_bytes()`.
+pub const CONFIG_BLOB_SIZE: usize = 17;
+
+/// Size in bytes of an entry of the MSI-X table.
+pub const MSIX_TABLE_ENTRY_SIZE: u64 = 16;
+
+/// Offset of the lower 32 bits of the message address in an MSI-X table entry.
+pub const MSIX_MSG_ADDR_LO: u64 = 0;
+/// Offset of the upper 32 bits of the message address in an MSI-X table entry.
+pub const MSIX_MSG_ADDR_HI: u64 = 4;
+/// Offset of the message data in an MSI-X table entry.
+pub const MSIX_MSG_DATA: u64 = 8;
+/// Offset of the vector control in an MSI-X table entry.
+pub const MSIX_VECTOR_CTRL: u64 = 12;
+
+/// Mask bit of the vector control of an MSI-X table entry.
+pub const MSIX_VECTOR_CTRL_MASK_BIT: u32 = 0x1;
+
+/// Decode an access of `len` bytes at `offset` of the MSI-X table into the index of the
+/// accessed entry and the offset of the first accessed field in the entry.
+///
+/// Only naturally aligned DWORD and QWORD accesses are valid.
+pub(crate) fn msix_table_access(offset: u64, len: usize) -> Result<(usize, u64)> {
+    if (len != 4 && len != 8) || offset & (len as u64 - 1) != 0 {
+        return Err(Error::InvalidConfiguration);
+    }
+    let index = VectorIndex::from_table_offset(offset)?;
+    Ok((index.into(), offset % MSIX_TABLE_ENTRY_SIZE))
+}
+
+/// Index of an entry of an MSI-X table, from 0 to `MSIX_MAX_VECTORS - 1`.
+///
+/// Conversions from out of range values fail with `Error::InvalidConfiguration`, and the
+/// arithmetic on the index is done on 64 bits so that it cannot overflow on 32-bit hosts.
+#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct VectorIndex(u16);
+
+impl VectorIndex {
+    /// Create the index of the index-th entry of an MSI-X table.
+    pub fn new(index: u16) -> Result<Self> {
+        if usize::from(index) < MSIX_MAX_VECTORS {
+            Ok(VectorIndex(index))
+        } else {
+            Err(Error::InvalidConfiguration)
+        }
+    }
+
+    /// Return the index of the entry of an MSI-X table containing the byte at `offset`.
+    pub fn from_table_offset(offset: u64) -> Result<Self> {
+        let index = u16::try_from(offset / MSIX_TABLE_ENTRY_SIZE)
+            .map_err(|_| Error::InvalidConfiguration)?;
+        Self::new(index)
+    }
+
+    /// Return the value of the index.
+    pub fn get(self) -> u16 {
+        self.0
+    }
+
+    /// Return the offset of the entry in the MSI-X table.
+    pub fn table_offset(self) -> u64 {
+        u64::from(self.0) * MSIX_TABLE_ENTRY_SIZE
+    }
+}
+
+impl TryFrom<usize> for VectorIndex {
+    type Error = Error;
+
+    fn try_from(index: usize) -> Result<Self> {
+        u16::try_from(index)
+            .map_err(|_| Error::InvalidConfiguration)
+            .and_then(Self::new)
+    }
+}
+
+impl From<VectorIndex> for usize {
+    fn from(index: VectorIndex) -> usize {
+        usize::from(index.0)
+    }
+}
+
+fn vector_mask(count: usize) -> Result<u32> {
+    if !count.is_power_of_two() || count > MSI_MAX_VECTORS {
+        return Err(Error::InvalidConfiguration);
+    }
+    Ok(count as u32 - 1)
+}
+
+/// Return the message data of the index-th vector of a multiple message MSI capability with
+/// `count` enabled vectors.
+///
+/// The device encodes the vector index in the low `log2(count)` bits of the message data
+/// programmed by the guest in `base`. Fails if `count` is not a power of two no larger than
+/// `MSI_MAX_VECTORS` or if `index` is not smaller than `count`.
+pub fn vector_data(base: u32, index: usize, count: usize) -> Result<u32> {
+    let mask = vector_mask(count)?;
+    if index >= count {
+        return Err(Error::InvalidConfiguration);
+    }
+    Ok((base & !mask) | index as u32)
+}
+
+/// Return the index of the vector encoded in the message data of a multiple message MSI
+/// capability with `count` enabled vectors.
+///
+/// This is the inverse of `vector_data()`.
+pub fn vector_index(data: u32, count: usize) -> Result<usize> {
+    Ok((data & vector_mask(count)?) as usize)
+}
+
+impl MsiIrqConfig {
+    /// Return the power-on configuration, with all the fields cleared.
+    ///
+    /// Unlike `Default::default()`, this can be used to initialize constants and statics.
+    pub const fn zeroed() -> Self {
+        MsiIrqConfig {
+            high_addr: 0,
+            low_addr: 0,
+            data: 0,
+            devid: 0,
+            trigger_mode: TriggerMode::Edge,
+        }
+    }
+
+    /// Return the configuration of a virtio queue interrupt delivering `vector` to the local
+    /// APIC with ID `cpu` on x86 platforms.
+    ///
+    /// The message uses the fixed delivery mode, physical destination mode and edge trigger
+    /// mode. Fields can be overridden with the struct update syntax.
+    pub fn virtio_queue(cpu: u8, vector: u8) -> Self {
+        MsiIrqConfig {
+            low_addr: MSI_ADDR_REGION_BASE | (u32::from(cpu) << 12),
+            data: u32::from(vector),
+            ..Self::zeroed()
+        }
+    }
+
+    /// Encode the configuration as the little endian `low_addr`, `high_addr`, `data` and
+    /// `devid`, followed by the trigger mode (0 for edge, 1 for level).
+    pub fn to_le_bytes(&self) -> [u8; CONFIG_BLOB_SIZE] {
+        let mut bytes = [0u8; CONFIG_BLOB_SIZE];
+        for (chunk, value) in
+            bytes
+                .chunks_exact_mut(4)
+                .zip([self.low_addr, self.high_addr, self.data, self.devid])
+        {
+            chunk.copy_from_slice(&value.to_le_bytes());
+        }
+        bytes[16] = match self.trigger_mode {
+            TriggerMode::Edge => 0,
+            TriggerMode::Level => 1,
+        };
+        bytes
+    }
+
+    /// Decode a configuration encoded with `to_le_bytes()`.
+    ///
+    /// Fails with `Error::InvalidConfiguration` if the trigger mode is invalid.
+    pub fn from_le_bytes(bytes: &[u8; CONFIG_BLOB_SIZE]) -> Result<Self> {
+        let dword =
+            |i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        let trigger_mode = match bytes[16] {
+            0 => TriggerMode::Edge,
+            1 => TriggerMode::Level,
+            _ => return Err(Error::InvalidConfiguration),
+        };
+        Ok(MsiIrqConfig {
+            low_addr: dword(0),
+            high_addr: dword(4),
+            data: dword(8),
+            devid: dword(12),
+            trigger_mode,
+        })
+    }
+
+    /// Return the 64-bit message address composed of `high_addr` and `low_addr`.
+    pub fn address(&self) -> u64 {
+        (u64::from(self.high_addr) << 32) | u64::from(self.low_addr)
+    }
+
+    /// Return the destination APIC ID of the message on x86 platforms.
+    ///
+    /// Bits 7:0 of the ID are encoded in bits 19:12 of the message address. Larger IDs are
+    /// encoded either in the x2APIC format, with bits 31:8 of the ID in bits 31:8 of
+    /// `high_addr`, or with the extended destination ID, holding bits 14:8 of the ID in bits
+    /// 11:5 of the message address. `check_destination()` rejects messages using both.
+    pub fn target_cpu(&self) -> u32 {
+        ((self.low_addr >> 12) & 0xff)
+            | (((self.low_addr >> 5) & 0x7f) << 8)
+            | (self.high_addr & 0xffff_ff00)
+    }
+
+    /// Set the destination APIC ID of the message on x86 platforms.
+    ///
+    /// IDs that don't fit in 8 bits are encoded with the extended destination ID when they fit
+    /// in 15 bits, and in the x2APIC format otherwise (see `target_cpu()`).
+    pub fn set_target_cpu(&mut self, cpu: u32) -> Result<()> {
+        let (ext_id, x2apic_id) = if cpu <= 0x7fff {
+            (cpu >> 8, 0)
+        } else {
+            (0, cpu & 0xffff_ff00)
+        };
+        self.low_addr =
+            (self.low_addr & !(0xff << 12 | 0x7f << 5)) | ((cpu & 0xff) << 12) | (ext_id << 5);
+        self.high_addr = (self.high_addr & 0xff) | x2apic_id;
+        Ok(())
+    }
+
+    /// Check that the message address falls in the 0xFEEx_xxxx interrupt region of x86
+    /// platforms.
+    ///
+    /// Implementations delivering interrupts to x86 local APICs should call this from
+    /// `update()`, so that invalid addresses are rejected with `Error::InvalidConfiguration`
+    /// instead of by the hypervisor. Other platforms don't have this constraint.
+    pub fn check_x86_address(&self) -> Result<()> {
+        if self.low_addr & MSI_ADDR_REGION_MASK == MSI_ADDR_REGION_BASE {
+            Ok(())
+        } else {
+            Err(Error::InvalidConfiguration)
+        }
+    }
+
+    /// Check that the destination of the message is an online vCPU of `topology`, and that
+    /// it doesn't use both the extended destination ID and the x2APIC format.
+    ///
+    /// Implementations that have a `CpuTopology` should call this from `update()` to reject
+    /// routes to offline vCPUs with `Error::InvalidConfiguration`.
+    pub fn check_destination(&self, topology: &dyn CpuTopology) -> Result<()> {
+        let ext_id = self.low_addr & (0x7f << 5) != 0;
+        let x2apic_id = self.high_addr & 0xffff_ff00 != 0;
+        if !(ext_id && x2apic_id) && topology.is_online(self.target_cpu()) {
+            Ok(())
+        } else {
+            Err(Error::InvalidConfiguration)
+        }
+    }
+
+    /// Check that the message carries a device ID if `required` by the backend.
+    ///
+    /// Implementations should call this from `update()` with the value of
+    /// `InterruptSourceGroup::requires_devid()`.
+    pub fn check_devid(&self, required: bool) -> Result<()> {
+        if required && self.devid == 0 {
+            Err(Error::InvalidConfiguration)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Return the interrupt vector encoded in bits 7:0 of the message data.
+    pub fn vector(&self) -> u8 {
+        (self.data & 0xff) as u8
+    }
+
+    /// Return the priority class of the interrupt vector, i.e. its upper 4 bits.
+    pub fn priority_class(&self) -> u8 {
+        self.vector() >> 4
+    }
+
+    /// Return the delivery mode encoded in bits 10:8 of the message data.
+    pub fn delivery_mode(&self) -> u8 {
+        ((self.data >> 8) & 0x7) as u8
+    }
+}
+
+/// Location of the MSI-X table and of the Pending Bit Array (PBA) in the BARs of a PCI device.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct MsixLayout {
+    /// Index of the BAR containing the MSI-X table.
+    pub table_bar: u8,
+    /// Offset of the MSI-X table in its BAR.
+    pub table_offset: u32,
+    /// Index of the BAR containing the PBA.
+    pub pba_bar: u8,
+    /// Offset of the PBA in its BAR.
+    pub pba_offset: u32,
+}
+
+impl MsixLayout {
+    /// Check that the BAR indices are valid (0 to 5) and that the offsets are QWORD aligned.
+    pub fn check(&self) -> Result<()> {
+        if self.table_bar > 5
+            || self.pba_bar > 5
+            || self.table_offset & 0x7 != 0
+            || self.pba_offset & 0x7 != 0
+        {
+            return Err(Error::InvalidConfiguration);
+        }
+        Ok(())
+    }
+
+    /// Return the value of the Table Offset/Table BIR register of the MSI-X capability.
+    pub fn table_register(&self) -> u32 {
+        self.table_offset | u32::from(self.table_bar & 0x7)
+    }
+
+    /// Return the value of the PBA Offset/PBA BIR register of the MSI-X capability.
+    pub fn pba_register(&self) -> u32 {
+        self.pba_offset | u32::from(self.pba_bar & 0x7)
+    }
+
+    fn from_registers(table: u32, pba: u32) -> Self {
+        MsixLayout {
+            table_bar: (table & 0x7) as u8,
+            table_offset: table & !0x7,
+            pba_bar: (pba & 0x7) as u8,
+            pba_offset: pba & !0x7,
+        }
+    }
+}
+
+/// Storage for the `MsixLayout` of a group, backing `InterruptSourceGroup::set_msix_layout()`
+/// and `InterruptSourceGroup::msix_layout()` without a lock.
+#[derive(Debug, Default)]
+pub(crate) struct MsixLayoutCell {
+    // Table register in the high 32 bits and PBA register in the low 32 bits.
+    registers: AtomicU64,
+    set: AtomicBool,
+}
+
+impl MsixLayoutCell {
+    pub(crate) fn set(&self, layout: MsixLayout) -> Result<()> {
+        layout.check()?;
+        let registers =
+            (u64::from(layout.table_register()) << 32) | u64::from(layout.pba_register());
+        self.registers.store(registers, Ordering::Release);
+        self.set.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    pub(crate) fn get(&self) -> Option<MsixLayout> {
+        if !self.set.load(Ordering::Acquire) {
+            return None;
+        }
+        let registers = self.registers.load(Ordering::Acquire);
+        Some(MsixLayout::from_registers(
+            (registers >> 32) as u32,
+            registers as u32,
+        ))
+    }
+}
+
+/// Trait for providers of the vCPU topology of the guest.
+pub trait CpuTopology: Send + Sync {
+    /// Return whether the vCPU with the APIC ID `apic_id` is online.
+    fn is_online(&self, apic_id: u32) -> bool;
+}
+
+/// Fields that differ between two snapshots of an MSI interrupt.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct ConfigDiff {
+    /// The message address changed.
+    pub address: bool,
+    /// The message data changed.
+    pub data: bool,
+    /// The device ID changed.
+    pub devid: bool,
+    /// The trigger mode changed.
+    pub trigger_mode: bool,
+    /// The interrupt was enabled or disabled.
+    pub enabled: bool,
+}
+
+impl ConfigDiff {
+    /// Return the differences between the snapshots `a` and `b`.
+    pub fn between(a: &ConfigSnapshot<MsiIrqConfig>, b: &ConfigSnapshot<MsiIrqConfig>) -> Self {
+        ConfigDiff {
+            address: a.config.address() != b.config.address(),
+            data: a.config.data != b.config.data,
+            devid: a.config.devid != b.config.devid,
+            trigger_mode: a.config.trigger_mode != b.config.trigger_mode,
+            enabled: a.enabled != b.enabled,
+        }
+    }
+
+    /// Return whether the snapshots are identical.
+    pub fn is_empty(&self) -> bool {
+        *self == ConfigDiff::default()
+    }
+}
+
+/// Return the index and the differences of every interrupt whose configuration or enable
+/// state differs between the snapshots `a` and `b`, e.g. returned by
+/// `InterruptSourceGroup::snapshot_configs()`.
+///
+/// Interrupts missing from one of the snapshots are compared against a disabled interrupt
+/// with the power-on configuration returned by `MsiIrqConfig::zeroed()`.
+pub fn diff_configs(
+    a: &[ConfigSnapshot<MsiIrqConfig>],
+    b: &[ConfigSnapshot<MsiIrqConfig>],
+) -> Vec<(usize, ConfigDiff)> {
+    let zeroed = ConfigSnapshot {
+        config: MsiIrqConfig::zeroed(),
+        enabled: false,
+    };
+    (0..a.len().max(b.len()))
+        .map(|index| {
+            let a = a.get(index).unwrap_or(&zeroed);
+            let b = b.get(index).unwrap_or(&zeroed);
+            (index, ConfigDiff::between(a, b))
+        })
+        .filter(|(_, diff)| !diff.is_empty())
+        .collect()
+}
+
+/// Pending bits of a group of MSI-X interrupts, laid out like the Pending Bit Array (PBA).
+///
+/// The trigger path of a masked interrupt sets its pending bit with `Release` ordering after
+/// recording anything the delivery of the interrupt depends on, and the PBA read path reads
+/// the bits with `Acquire` ordering. A reader that observes a pending bit therefore also
+/// observes the state written before the bit was set, and never sees a pending bit without a
+/// matching delivery.
+#[derive(Debug, Default)]
+pub struct PendingBits {
+    words: Vec<AtomicU64>,
+    len: usize,
+    next: AtomicUsize,
+}
+
+impl PendingBits {
+    /// Create pending bits for `len` interrupts, all cleared.
+    pub fn new(len: usize) -> Self {
+        let words = len / 64 + usize::from(len & 63 != 0);
+        PendingBits {
+            words: (0..words).map(|_| AtomicU64::new(0)).collect(),
+            len,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Return the number of interrupts tracked.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Return whether no interrupt is tracked.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn locate(&self, index: usize) -> Result<(&AtomicU64, u64)> {
+        if index >= self.len {
+            return Err(Error::InvalidConfiguration);
+        }
+        Ok((&self.words[index / 64], 1 << (index % 64)))
+    }
+
+    /// Set the pending bit of the index-th interrupt and return whether it was already set.
+    pub fn set(&self, index: usize) -> Result<bool> {
+        let (word, bit) = self.locate(index)?;
+        Ok(word.fetch_or(bit, Ordering::Release) & bit != 0)
+    }
+
+    /// Clear the pending bit of the index-th interrupt and return whether it was set.
+    ///
+    /// A `true` result means the caller is responsible for delivering the pending interrupt.
+    pub fn clear(&self, index: usize) -> Result<bool> {
+        let (word, bit) = self.locate(index)?;
+        Ok(word.fetch_and(!bit, Ordering::AcqRel) & bit != 0)
+    }
+
+    /// Return whether the index-th interrupt is pending.
+    pub fn is_pending(&self, index: usize) -> Result<bool> {
+        let (word, bit) = self.locate(index)?;
+        Ok(word.load(Ordering::Acquire) & bit != 0)
+    }
+
+    /// Return the index of the next pending interrupt in round-robin order, or `None` if no
+    /// interrupt is pending.
+    ///
+    /// The search starts after the interrupt returned by the previous call, so that every
+    /// pending interrupt is eventually returned regardless of the others. The pending bit is
+    /// not cleared.
+    pub fn next_pending_round_robin(&self) -> Option<usize> {
+        let start = self.next.load(Ordering::Relaxed);
+        let found = (0..self.len)
+            .map(|offset| (start + offset) % self.len)
+            .find(|&index| self.is_pending(index).unwrap_or(false))?;
+        self.next.store(found + 1, Ordering::Relaxed);
+        Some(found)
+    }
+
+    /// Return the index-th QWORD of the PBA, or `None` if the index is out of bounds.
+    pub fn qword(&self, index: usize) -> Option<u64> {
+        self.words
+            .get(index)
+            .map(|word| word.load(Ordering::Acquire))
+    }
+
+    /// Emulate a guest read of `data.len()` bytes at `offset` of the PBA.
+    ///
+    /// Fails with `Error::InvalidConfiguration` for accesses that are not naturally aligned
+    /// DWORD or QWORD accesses, or beyond the end of the PBA.
+    pub fn handle_pba_read(&self, offset: u64, data: &mut [u8]) -> Result<()> {
+        let len = data.len();
+        if (len != 4 && len != 8) || offset & (len as u64 - 1) != 0 {
+            return Err(Error::InvalidConfiguration);
+        }
+        let index = usize::try_from(offset / 8).map_err(|_| Error::InvalidConfiguration)?;
+        let qword = self.qword(index).ok_or(Error::InvalidConfiguration)?;
+        let start = (offset % 8) as usize;
+        data.copy_from_slice(&qword.to_le_bytes()[start..start + len]);
+        Ok(())
+    }
+
+    /// Emulate a guest write at `offset` of the PBA.
+    ///
+    /// The PBA is read-only, so the write is ignored.
+    pub fn handle_pba_write(&self, _offset: u64, _data: &[u8]) {}
+}
+
+/// Trait for components that deliver MSI messages by writing them to a memory location.
+///
+/// This allows delivering interrupts through a mechanism other than the routing of the
+/// hypervisor, e.g. a device-specific MMIO region of a passthrough accelerator.
+pub trait MessageSink: Send + Sync {
+    /// Write the message `data` at `addr`.
+    fn write_message(&self, addr: u64, data: u32) -> Result<()>;
+}
+
+impl<T: MessageSink + ?Sized> MessageSink for Arc<T> {
+    fn write_message(&self, addr: u64, data: u32) -> Result<()> {
+        (**self).write_message(addr, data)
+    }
+}
+
+impl MessageSink for Box<dyn MessageSink> {
+    fn write_message(&self, addr: u64, data: u32) -> Result<()> {
+        (**self).write_message(addr, data)
+    }
+}
+
+/// `MessageSink` forwarding the messages to another sink that can be replaced at runtime.
+///
+/// This allows moving a `SinkMsiInterrupt` between delivery paths (e.g. from a fast path to an
+/// instrumented one) while keeping its configuration, enable, mask and pending state. Messages
+/// being written while the sink is replaced complete on the previous sink, and later messages
+/// go to the new one, so no message is lost.
+#[cfg(feature = "std")]
+pub struct SwappableSink {
+    sink: RwLock<Box<dyn MessageSink>>,
+}
+
+#[cfg(feature = "std")]
+impl SwappableSink {
+    /// Create a new sink forwarding the messages to `sink`.
+    pub fn new(sink: Box<dyn MessageSink>) -> Self {
+        SwappableSink {
+            sink: RwLock::new(sink),
+        }
+    }
+
+    /// Forward the next messages to `sink` and return the previous sink.
+    pub fn swap(&self, sink: Box<dyn MessageSink>) -> Box<dyn MessageSink> {
+        core::mem::replace(
+            &mut *self.sink.write().expect("failed to acquire lock"),
+            sink,
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl MessageSink for SwappableSink {
+    fn write_message(&self, addr: u64, data: u32) -> Result<()> {
+        self.sink
+            .read()
+            .expect("failed to acquire lock")
+            .write_message(addr, data)
+    }
+}
+
+/// Callback returning the current value of the Task Priority Register of the guest.
+///
+/// With the `panic-safe-callbacks` feature, interrupts are not deferred when the provider
+/// panics.
+pub type TprProvider = Arc<dyn Fn() -> u8 + Send + Sync>;
+
+#[cfg(feature = "std")]
+#[derive(Default)]
+struct SinkState {
+    config: MsiIrqConfig,
+    enabled: bool,
+    masked: bool,
+    pending: bool,
+    tpr: Option<TprProvider>,
+}
+
+// Like a local APIC, only deliver vectors whose priority class is above the one of the TPR.
+#[cfg(feature = "std")]
+fn deferred_by_tpr(tpr: Option<&TprProvider>, config: &MsiIrqConfig) -> bool {
+    match tpr.and_then(|tpr| call_callback(|| tpr())) {
+        Some(tpr) => config.priority_class() <= tpr >> 4,
+        None => false,
+    }
+}
+
+/// MSI interrupt delivered by writing the configured message to a `MessageSink`.
+///
+/// Triggering a disabled interrupt is discarded, while triggering a masked interrupt marks it
+/// as pending until it is unmasked. If a `TprProvider` is set, triggering an interrupt whose
+/// priority is not above the Task Priority Register of the guest also marks it as pending,
+/// until `tpr_lowered()` is called.
+#[cfg(feature = "std")]
+pub struct SinkMsiInterrupt<S: MessageSink> {
+    id: InterruptId,
+    kind: InterruptSourceKind,
+    sink: S,
+    state: Mutex<SinkState>,
+}
+
+#[cfg(feature = "std")]
+impl<S: MessageSink> SinkMsiInterrupt<S> {
+    /// Create a new disabled and unmasked interrupt of type `kind` delivered through `sink`.
+    pub fn new(kind: InterruptSourceKind, sink: S) -> Self {
+        SinkMsiInterrupt {
+            id: InterruptId::next(),
+            kind,
+            sink,
+            state: Mutex::new(SinkState::default()),
+        }
+    }
+
+    /// Return the sink through which the interrupt is delivered.
+    pub fn sink(&self) -> &S {
+        &self.sink
+    }
+
+    /// Return whether the interrupt was triggered while masked and not yet delivered.
+    pub fn is_pending(&self) -> bool {
+        self.state.lock().expect("failed to acquire lock").pending
+    }
+
+    /// Set the provider of the Task Priority Register of the guest, gating the delivery of
+    /// the interrupt as a software emulated local APIC would.
+    pub fn set_tpr_provider(&self, provider: TprProvider) {
+        self.state.lock().expect("failed to acquire lock").tpr = Some(provider);
+    }
+
+    /// Deliver the interrupt if it is pending and no longer deferred by the Task Priority
+    /// Register of the guest, and return whether it was delivered.
+    ///
+    /// This should be called when the guest lowers its TPR.
+    pub fn tpr_lowered(&self) -> Result<bool> {
+        self.deliver_pending()
+    }
+
+    // The sink and the TPR provider are always called without holding the state lock, so that
+    // they can call back into the interrupt.
+    fn write(&self, config: &MsiIrqConfig) -> Result<()> {
+        self.sink.write_message(config.address(), config.data)
+    }
+
+    // Deliver the interrupt if it is pending, unmasked and not deferred by the TPR, and return
+    // whether it was delivered. The pending state is checked again after reading the TPR, since
+    // it may have been delivered concurrently.
+    fn deliver_pending(&self) -> Result<bool> {
+        let (config, tpr) = {
+            let state = self.state.lock().expect("failed to acquire lock");
+            if !state.pending || state.masked {
+                return Ok(false);
+            }
+            (state.config, state.tpr.clone())
+        };
+        if deferred_by_tpr(tpr.as_ref(), &config) {
+            return Ok(false);
+        }
+        let config = {
+            let mut state = self.state.lock().expect("failed to acquire lock");
+            if !state.pending || state.masked {
+                return Ok(false);
+            }
+            state.pending = false;
+            state.config
+        };
+        self.write_pending(&config)?;
+        Ok(true)
+    }
+
+    // Deliver an interrupt whose pending state was cleared by the caller. The pending state is
+    // cleared before writing so that concurrent callers don't deliver it twice, and restored
+    // if the write fails so that the interrupt is not lost.
+    fn write_pending(&self, config: &MsiIrqConfig) -> Result<()> {
+        let result = self.write(config);
+        if result.is_err() {
+            let mut state = self.state.lock().expect("failed to acquire lock");
+            state.pending = state.enabled;
+        }
+        result
+    }
+}
+
+#[cfg(feature = "std")]
+impl<S: MessageSink> Interrupt for SinkMsiInterrupt<S> {
+    fn kind(&self) -> InterruptSourceKind {
+        self.kind
+    }
+
+    fn id(&self) -> InterruptId {
+        self.id
+    }
+
+    fn enable(&self) -> Result<bool> {
+        let mut state = self.state.lock().expect("failed to acquire lock");
+        Ok(!core::mem::replace(&mut state.enabled, true))
+    }
+
+    fn disable(&self) -> Result<bool> {
+        let mut state = self.state.lock().expect("failed to acquire lock");
+        state.pending = false;
+        Ok(core::mem::replace(&mut state.enabled, false))
+    }
+
+    fn is_enabled(&self) -> Result<bool> {
+        Ok(self.state.lock().expect("failed to acquire lock").enabled)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<S: MessageSink> EdgeInterrupt for SinkMsiInterrupt<S> {
+    fn trigger(&self) -> Result<()> {
+        let (config, tpr) = {
+            let mut state = self.state.lock().expect("failed to acquire lock");
+            if !state.enabled {
+                return Ok(());
+            }
+            if state.masked {
+                state.pending = true;
+                return Ok(());
+            }
+            (state.config, state.tpr.clone())
+        };
+        if deferred_by_tpr(tpr.as_ref(), &config) {
+            // The interrupt may have been disabled while the TPR was read.
+            let mut state = self.state.lock().expect("failed to acquire lock");
+            state.pending = state.enabled;
+            return Ok(());
+        }
+        self.write(&config)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<S: MessageSink> ConfigurableInterrupt for SinkMsiInterrupt<S> {
+    type Cfg = MsiIrqConfig;
+
+    fn update(&self, config: &MsiIrqConfig) -> Result<()> {
+        self.validate(config)?;
+        self.state.lock().expect("failed to acquire lock").config = *config;
+        Ok(())
+    }
+
+    /// Any configuration is accepted, since the sink defines how the message is delivered.
+    fn validate(&self, _config: &MsiIrqConfig) -> Result<()> {
+        Ok(())
+    }
+
+    fn get_config(&self) -> Result<MsiIrqConfig> {
+        Ok(self.state.lock().expect("failed to acquire lock").config)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<S: MessageSink> MaskableInterrupt for SinkMsiInterrupt<S> {
+    fn mask(&self) -> Result<bool> {
+        let mut state = self.state.lock().expect("failed to acquire lock");
+        Ok(!core::mem::replace(&mut state.masked, true))
+    }
+
+    fn unmask(&self) -> Result<Unmasked> {
+        self.state.lock().expect("failed to acquire lock").masked = false;
+        Ok(Unmasked {
+            delivered_pending: self.deliver_pending()?,
+        })
+    }
+
+    fn is_masked(&self) -> Result<bool> {
+        Ok(self.state.lock().expect("failed to acquire lock").masked)
+    }
 }
 
 /// Trait for defining properties of MSI interrupts.
@@ -30,3 +882,830 @@ pub trait MsiInterrupt: ConfigurableInterrupt<Cfg = MsiIrqConfig> + MaskableInte
 
 /// Blanket implementation for Interrupts that use a MsiIrqConfig.
 impl<T> MsiInterrupt for T where T: ConfigurableInterrupt<Cfg = MsiIrqConfig> + MaskableInterrupt {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Mutex;
+
+    use crate::interrupt::fixed::StaticInterruptGroup;
+    #[cfg(feature = "std")]
+    use crate::interrupt::EdgeInterrupt;
+    use crate::interrupt::{
+        Interrupt, InterruptId, InterruptSourceGroup, InterruptSourceKind, LevelInterrupt, Unmasked,
+    };
+
+    /// MSI interrupt counting how many times it was deasserted.
+    #[derive(Default)]
+    struct LevelMsiInterrupt {
+        id: InterruptId,
+        config: Mutex<MsiIrqConfig>,
+        cleared: AtomicUsize,
+    }
+
+    impl Interrupt for LevelMsiInterrupt {
+        fn kind(&self) -> InterruptSourceKind {
+            InterruptSourceKind::MsiX
+        }
+
+        fn id(&self) -> InterruptId {
+            self.id
+        }
+    }
+
+    impl ConfigurableInterrupt for LevelMsiInterrupt {
+        type Cfg = MsiIrqConfig;
+
+        fn update(&self, config: &MsiIrqConfig) -> Result<()> {
+            *self.config.lock().unwrap() = *config;
+            Ok(())
+        }
+
+        fn validate(&self, config: &MsiIrqConfig) -> Result<()> {
+            config.check_destination(&FirstCpus(u32::MAX))
+        }
+
+        fn get_config(&self) -> Result<MsiIrqConfig> {
+            Ok(*self.config.lock().unwrap())
+        }
+    }
+
+    impl MaskableInterrupt for LevelMsiInterrupt {
+        fn mask(&self) -> Result<bool> {
+            Ok(true)
+        }
+
+        fn unmask(&self) -> Result<Unmasked> {
+            Ok(Unmasked::default())
+        }
+    }
+
+    impl LevelInterrupt for LevelMsiInterrupt {
+        fn assert(&self) -> Result<()> {
+            Ok(())
+        }
+
+        fn clear(&self) -> Result<()> {
+            self.cleared.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[derive(Default)]
+    struct RecordingSink {
+        messages: Mutex<Vec<(u64, u32)>>,
+    }
+
+    #[cfg(feature = "std")]
+    impl MessageSink for RecordingSink {
+        fn write_message(&self, addr: u64, data: u32) -> Result<()> {
+            self.messages.lock().unwrap().push((addr, data));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_vector_data() {
+        assert_eq!(vector_data(0x4020, 0, 1).unwrap(), 0x4020);
+        assert_eq!(vector_data(0x4020, 5, 8).unwrap(), 0x4025);
+        assert_eq!(vector_data(0x4027, 2, 4).unwrap(), 0x4026);
+        assert_eq!(vector_index(0x4025, 8).unwrap(), 5);
+        assert_eq!(vector_index(0x4025, 1).unwrap(), 0);
+
+        assert!(vector_data(0x4020, 4, 4).is_err());
+        assert!(vector_data(0x4020, 0, 3).is_err());
+        assert!(vector_data(0x4020, 0, 0).is_err());
+        assert!(vector_data(0x4020, 0, 64).is_err());
+        assert!(vector_index(0x4025, 6).is_err());
+    }
+
+    #[test]
+    fn test_zeroed_config() {
+        const ZEROED: MsiIrqConfig = MsiIrqConfig::zeroed();
+        assert_eq!(ZEROED, MsiIrqConfig::default());
+        assert_eq!(ZEROED.address(), 0);
+    }
+
+    #[test]
+    fn test_config_fields() {
+        let config = MsiIrqConfig {
+            low_addr: 0xfee0_3000,
+            data: 0x4_0441,
+            ..Default::default()
+        };
+        assert_eq!(config.target_cpu(), 3);
+        assert_eq!(config.vector(), 0x41);
+        assert_eq!(config.delivery_mode(), 4);
+    }
+
+    #[test]
+    fn test_virtio_queue_config() {
+        let config = MsiIrqConfig::virtio_queue(3, 0x41);
+        assert!(config.check_x86_address().is_ok());
+        assert_eq!(config.target_cpu(), 3);
+        assert_eq!(config.vector(), 0x41);
+        assert_eq!(config.delivery_mode(), 0);
+        assert_eq!(config.trigger_mode, TriggerMode::Edge);
+        assert_eq!(config.high_addr, 0);
+    }
+
+    #[test]
+    fn test_vector_index() {
+        let index = VectorIndex::new(2047).unwrap();
+        assert_eq!(index.table_offset(), 2047 * 16);
+        assert_eq!(
+            VectorIndex::from_table_offset(2047 * 16 + 12).unwrap(),
+            index
+        );
+        assert_eq!(usize::from(index), 2047);
+        assert_eq!(VectorIndex::try_from(3usize).unwrap().get(), 3);
+
+        assert!(VectorIndex::new(2048).is_err());
+        assert!(VectorIndex::try_from(0x1_0001usize).is_err());
+        assert!(VectorIndex::from_table_offset(2048 * 16).is_err());
+        assert!(VectorIndex::from_table_offset(u64::MAX).is_err());
+        assert!(msix_table_access(u64::MAX - 7, 8).is_err());
+    }
+
+    #[test]
+    fn test_msix_layout() {
+        let layout = MsixLayout {
+            table_bar: 2,
+            table_offset: 0x2000,
+            pba_bar: 2,
+            pba_offset: 0x3000,
+        };
+        assert!(layout.check().is_ok());
+        assert_eq!(layout.table_register(), 0x2002);
+        assert_eq!(layout.pba_register(), 0x3002);
+
+        assert!(MsixLayout {
+            table_bar: 6,
+            ..layout
+        }
+        .check()
+        .is_err());
+        assert!(MsixLayout {
+            pba_offset: 0x3004,
+            ..layout
+        }
+        .check()
+        .is_err());
+
+        let group = StaticInterruptGroup::new([LevelMsiInterrupt::default()]);
+        assert_eq!(group.msix_layout(), None);
+        group.set_msix_layout(layout).unwrap();
+        assert_eq!(group.msix_layout(), Some(layout));
+        let invalid = MsixLayout {
+            table_offset: 0x2001,
+            ..layout
+        };
+        assert!(group.set_msix_layout(invalid).is_err());
+        assert_eq!(group.msix_layout(), Some(layout));
+    }
+
+    #[test]
+    fn test_mixed_trigger_modes() {
+        let group = StaticInterruptGroup::new([(); 3].map(|_| LevelMsiInterrupt::default()));
+        let modes = group.trigger_modes().unwrap();
+        assert!(modes.edge && !modes.level);
+
+        let level = MsiIrqConfig {
+            trigger_mode: TriggerMode::Level,
+            ..Default::default()
+        };
+        group.get(1).unwrap().update(&level).unwrap();
+        assert!(group.trigger_modes().unwrap().is_mixed());
+
+        // Deasserting an edge triggered interrupt is a no-op.
+        group.deassert(0).unwrap();
+        assert_eq!(group.get(0).unwrap().cleared.load(Ordering::SeqCst), 0);
+        group.deassert(1).unwrap();
+        assert_eq!(group.get(1).unwrap().cleared.load(Ordering::SeqCst), 1);
+        assert!(group.deassert(3).is_err());
+    }
+
+    #[test]
+    fn test_mask_all_without_is_masked() {
+        // The snapshot relies on `mask()` alone, `LevelMsiInterrupt` doesn't report its mask
+        // state.
+        let group = StaticInterruptGroup::new([(); 2].map(|_| LevelMsiInterrupt::default()));
+        assert!(group.get(0).unwrap().is_masked().is_err());
+        let snapshot = group.mask_all_and_snapshot().unwrap();
+        assert_eq!(snapshot.is_masked(0), Some(false));
+        assert_eq!(snapshot.is_masked(1), Some(false));
+        group.restore_mask(snapshot).unwrap();
+    }
+
+    #[test]
+    fn test_config_blob() {
+        let mut src = StaticInterruptGroup::new([(); 2].map(|_| LevelMsiInterrupt::default()));
+        let config = MsiIrqConfig {
+            high_addr: 0x1,
+            low_addr: 0xfee0_1000,
+            data: 0x41,
+            devid: 0x10,
+            trigger_mode: TriggerMode::Level,
+        };
+        src.get(1).unwrap().update(&config).unwrap();
+        let blob = src.export_blob().unwrap();
+        assert_eq!(blob.len(), BLOB_HEADER_SIZE + 2 * CONFIG_BLOB_SIZE);
+        assert_eq!(u16::from_le_bytes([blob[0], blob[1]]), BLOB_VERSION);
+
+        let mut dst = StaticInterruptGroup::new([(); 2].map(|_| LevelMsiInterrupt::default()));
+        dst.import_blob(&blob).unwrap();
+        assert_eq!(
+            dst.get(0).unwrap().get_config().unwrap(),
+            MsiIrqConfig::zeroed()
+        );
+        assert_eq!(dst.get(1).unwrap().get_config().unwrap(), config);
+
+        // Truncated blobs, unknown versions and invalid configurations are rejected.
+        assert!(dst.import_blob(&blob[..blob.len() - 1]).is_err());
+        assert!(dst.import_blob(&blob[..3]).is_err());
+        let mut invalid = blob.clone();
+        invalid[0] = 2;
+        assert!(dst.import_blob(&invalid).is_err());
+        let mut invalid = blob.clone();
+        invalid[BLOB_HEADER_SIZE + 16] = 2;
+        assert!(dst.import_blob(&invalid).is_err());
+        // The number of interrupts must match.
+        assert!(src.import_blob(&blob[..BLOB_HEADER_SIZE]).is_err());
+        let mut empty = StaticInterruptGroup::new([(); 0].map(|_| LevelMsiInterrupt::default()));
+        assert!(empty.import_blob(&blob).is_err());
+    }
+
+    /// Group allocating `LevelMsiInterrupt`s.
+    #[derive(Default)]
+    struct LevelMsiGroup {
+        irqs: Vec<Arc<LevelMsiInterrupt>>,
+    }
+
+    impl InterruptSourceGroup for LevelMsiGroup {
+        type InterruptType = LevelMsiInterrupt;
+        type InterruptWrapper = Arc<LevelMsiInterrupt>;
+
+        fn len(&self) -> usize {
+            self.irqs.len()
+        }
+
+        fn enable(&self) -> Result<()> {
+            Ok(())
+        }
+
+        fn disable(&self) -> Result<()> {
+            Ok(())
+        }
+
+        fn get(&self, index: usize) -> Option<Arc<LevelMsiInterrupt>> {
+            self.irqs.get(index).cloned()
+        }
+
+        fn allocate_interrupts(&mut self, size: usize) -> Result<()> {
+            self.irqs
+                .extend((0..size).map(|_| Arc::new(LevelMsiInterrupt::default())));
+            Ok(())
+        }
+
+        fn free_interrupts(&mut self) -> Result<()> {
+            self.irqs.clear();
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_import_invalid_blob() {
+        let src = StaticInterruptGroup::new([(); 2].map(|_| LevelMsiInterrupt::default()));
+        let config = MsiIrqConfig {
+            data: 0x41,
+            ..Default::default()
+        };
+        // The second configuration mixes the two encodings of large APIC IDs.
+        let mixed = MsiIrqConfig {
+            low_addr: 0xfee0_0020,
+            high_addr: 0x100,
+            ..Default::default()
+        };
+        src.get(0).unwrap().update(&config).unwrap();
+        src.get(1).unwrap().update(&mixed).unwrap();
+        let blob = src.export_blob().unwrap();
+
+        // No configuration is changed if one of them is invalid.
+        let mut dst = StaticInterruptGroup::new([(); 2].map(|_| LevelMsiInterrupt::default()));
+        assert!(matches!(
+            dst.import_blob(&blob),
+            Err(Error::InvalidConfiguration)
+        ));
+        assert_eq!(
+            dst.get(0).unwrap().get_config().unwrap(),
+            MsiIrqConfig::zeroed()
+        );
+
+        // The interrupts allocated by a failed import are released.
+        let mut dst = LevelMsiGroup::default();
+        assert!(dst.import_blob(&blob).is_err());
+        assert!(dst.is_empty());
+        src.get(1).unwrap().update(&config).unwrap();
+        dst.import_blob(&src.export_blob().unwrap()).unwrap();
+        assert_eq!(dst.len(), 2);
+        assert_eq!(dst.get(1).unwrap().get_config().unwrap(), config);
+    }
+
+    #[test]
+    fn test_msix_table_size_field() {
+        let group = StaticInterruptGroup::new([(); 3].map(|_| LevelMsiInterrupt::default()));
+        assert_eq!(group.msix_table_size_field(), 2);
+        let group = StaticInterruptGroup::new([LevelMsiInterrupt::default()]);
+        assert_eq!(group.msix_table_size_field(), 0);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_msix_table_access() {
+        let group =
+            StaticInterruptGroup::new([(); 2].map(|_| {
+                SinkMsiInterrupt::new(InterruptSourceKind::MsiX, RecordingSink::default())
+            }));
+        let entry = MSIX_TABLE_ENTRY_SIZE;
+
+        group
+            .handle_table_write(entry, &0x1_fee0_1000u64.to_le_bytes())
+            .unwrap();
+        group
+            .handle_table_write(entry + MSIX_MSG_DATA, &0x41u32.to_le_bytes())
+            .unwrap();
+        let config = group.get(1).unwrap().get_config().unwrap();
+        assert_eq!(config.address(), 0x1_fee0_1000);
+        assert_eq!(config.data, 0x41);
+        assert_eq!(
+            group.get(0).unwrap().get_config().unwrap(),
+            MsiIrqConfig::zeroed()
+        );
+
+        group
+            .handle_table_write(entry + MSIX_VECTOR_CTRL, &1u32.to_le_bytes())
+            .unwrap();
+        assert!(group.get(1).unwrap().is_masked().unwrap());
+        let mut data = [0u8; 8];
+        group
+            .handle_table_read(entry + MSIX_MSG_DATA, &mut data)
+            .unwrap();
+        assert_eq!(u64::from_le_bytes(data), (1 << 32) | 0x41);
+        let mut data = [0u8; 4];
+        group
+            .handle_table_read(entry + MSIX_MSG_ADDR_HI, &mut data)
+            .unwrap();
+        assert_eq!(u32::from_le_bytes(data), 1);
+
+        group
+            .handle_table_write(entry + MSIX_VECTOR_CTRL, &0u32.to_le_bytes())
+            .unwrap();
+        assert!(!group.get(1).unwrap().is_masked().unwrap());
+
+        // Unaligned, invalid size and out of range accesses.
+        assert!(group.handle_table_write(entry + 2, &[0; 4]).is_err());
+        assert!(group.handle_table_write(entry + 4, &[0; 8]).is_err());
+        assert!(group.handle_table_write(entry, &[0; 2]).is_err());
+        assert!(group.handle_table_read(2 * entry, &mut [0; 4]).is_err());
+    }
+
+    struct FirstCpus(u32);
+
+    impl CpuTopology for FirstCpus {
+        fn is_online(&self, apic_id: u32) -> bool {
+            apic_id < self.0
+        }
+    }
+
+    #[test]
+    fn test_check_destination() {
+        let mut config = MsiIrqConfig {
+            low_addr: 0xfee0_1000,
+            ..Default::default()
+        };
+        assert!(config.check_destination(&FirstCpus(2)).is_ok());
+        config.low_addr = 0xfee0_2000;
+        assert!(config.check_destination(&FirstCpus(2)).is_err());
+
+        // Offline vCPUs above 255 don't alias low APIC IDs.
+        config.low_addr = 0xfee0_1020;
+        assert_eq!(config.target_cpu(), 0x101);
+        assert!(config.check_destination(&FirstCpus(2)).is_err());
+        assert!(config.check_destination(&FirstCpus(0x102)).is_ok());
+        config.low_addr = 0xfee0_1000;
+        config.high_addr = 0x100;
+        assert!(config.check_destination(&FirstCpus(0x102)).is_ok());
+        // The two encodings of large IDs can't be mixed.
+        config.low_addr = 0xfee0_0020;
+        assert!(config.check_destination(&FirstCpus(u32::MAX)).is_err());
+    }
+
+    #[test]
+    fn test_check_x86_address() {
+        let mut config = MsiIrqConfig {
+            low_addr: 0xfee0_f00c,
+            ..Default::default()
+        };
+        assert!(config.check_x86_address().is_ok());
+        config.low_addr = 0xfed0_0000;
+        assert!(config.check_x86_address().is_err());
+        assert!(MsiIrqConfig::zeroed().check_x86_address().is_err());
+    }
+
+    #[test]
+    fn test_diff_configs() {
+        let snapshot = |low_addr, data, enabled| ConfigSnapshot {
+            config: MsiIrqConfig {
+                low_addr,
+                data,
+                ..Default::default()
+            },
+            enabled,
+        };
+        let before = [
+            snapshot(0xfee0_0000, 0x41, true),
+            snapshot(0xfee0_1000, 0x42, true),
+            snapshot(0xfee0_1000, 0x43, false),
+        ];
+        let mut after = before.to_vec();
+        assert!(diff_configs(&before, &after).is_empty());
+
+        after[1].config.low_addr = 0xfee0_2000;
+        after[2].enabled = true;
+        after.push(ConfigSnapshot {
+            config: MsiIrqConfig {
+                devid: 0x10,
+                ..Default::default()
+            },
+            enabled: false,
+        });
+        assert_eq!(
+            diff_configs(&before, &after),
+            vec![
+                (
+                    1,
+                    ConfigDiff {
+                        address: true,
+                        ..Default::default()
+                    }
+                ),
+                (
+                    2,
+                    ConfigDiff {
+                        enabled: true,
+                        ..Default::default()
+                    }
+                ),
+                (
+                    3,
+                    ConfigDiff {
+                        devid: true,
+                        ..Default::default()
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_devid() {
+        let mut config = MsiIrqConfig::default();
+        assert!(config.check_devid(false).is_ok());
+        assert!(config.check_devid(true).is_err());
+        config.devid = 0x10;
+        assert!(config.check_devid(true).is_ok());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_sink_msi_interrupt() {
+        let irq = SinkMsiInterrupt::new(InterruptSourceKind::MsiX, RecordingSink::default());
+        assert_eq!(irq.kind(), InterruptSourceKind::MsiX);
+        irq.update(&MsiIrqConfig {
+            high_addr: 0x1,
+            low_addr: 0xfee0_0000,
+            data: 0x41,
+            ..Default::default()
+        })
+        .unwrap();
+
+        irq.trigger().unwrap();
+        assert!(irq.sink.messages.lock().unwrap().is_empty());
+
+        irq.enable().unwrap();
+        irq.trigger().unwrap();
+        assert!(irq.mask().unwrap());
+        assert!(!irq.mask().unwrap());
+        irq.trigger().unwrap();
+        irq.trigger().unwrap();
+        assert!(irq.is_pending());
+        assert!(irq.unmask().unwrap().delivered_pending);
+        assert!(!irq.is_pending());
+        assert!(!irq.unmask().unwrap().delivered_pending);
+        assert_eq!(
+            *irq.sink.messages.lock().unwrap(),
+            vec![(0x1_fee0_0000, 0x41), (0x1_fee0_0000, 0x41)]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_sink_msi_interrupt_failed_write() {
+        /// Sink failing the next write if `fail` is set.
+        #[derive(Default)]
+        struct FlakySink {
+            fail: core::sync::atomic::AtomicBool,
+            written: AtomicUsize,
+        }
+
+        impl MessageSink for FlakySink {
+            fn write_message(&self, _addr: u64, _data: u32) -> Result<()> {
+                if self.fail.swap(false, Ordering::SeqCst) {
+                    return Err(Error::InvalidConfiguration);
+                }
+                self.written.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            }
+        }
+
+        let irq = SinkMsiInterrupt::new(InterruptSourceKind::MsiX, FlakySink::default());
+        irq.enable().unwrap();
+        irq.mask().unwrap();
+        irq.trigger().unwrap();
+
+        // A pending interrupt that fails to be written stays pending.
+        irq.sink().fail.store(true, Ordering::SeqCst);
+        assert!(irq.unmask().is_err());
+        assert!(irq.is_pending());
+        irq.mask().unwrap();
+        assert!(irq.unmask().unwrap().delivered_pending);
+        assert!(!irq.is_pending());
+        assert_eq!(irq.sink().written.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_swappable_sink() {
+        let fast = Arc::new(RecordingSink::default());
+        let instrumented = Arc::new(RecordingSink::default());
+        let irq = SinkMsiInterrupt::new(
+            InterruptSourceKind::MsiX,
+            SwappableSink::new(Box::new(fast.clone())),
+        );
+        irq.update(&MsiIrqConfig::virtio_queue(0, 0x41)).unwrap();
+        irq.enable().unwrap();
+        irq.trigger().unwrap();
+        irq.mask().unwrap();
+        irq.trigger().unwrap();
+
+        irq.sink().swap(Box::new(instrumented.clone()));
+        assert!(irq.is_enabled().unwrap());
+        assert!(irq.is_pending());
+        assert!(irq.unmask().unwrap().delivered_pending);
+        irq.trigger().unwrap();
+        assert_eq!(fast.messages.lock().unwrap().len(), 1);
+        assert_eq!(
+            *instrumented.messages.lock().unwrap(),
+            vec![(0xfee0_0000, 0x41), (0xfee0_0000, 0x41)]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_tpr_gating() {
+        let irq = SinkMsiInterrupt::new(InterruptSourceKind::MsiX, RecordingSink::default());
+        irq.update(&MsiIrqConfig::virtio_queue(0, 0x41)).unwrap();
+        irq.enable().unwrap();
+        let tpr = Arc::new(AtomicUsize::new(0x40));
+        let provider = tpr.clone();
+        irq.set_tpr_provider(Arc::new(move || provider.load(Ordering::SeqCst) as u8));
+
+        // The priority class of the vector is not above the one of the TPR.
+        irq.trigger().unwrap();
+        assert!(irq.is_pending());
+        assert!(!irq.tpr_lowered().unwrap());
+        irq.mask().unwrap();
+        assert!(!irq.unmask().unwrap().delivered_pending);
+        assert!(irq.sink.messages.lock().unwrap().is_empty());
+
+        tpr.store(0x3f, Ordering::SeqCst);
+        assert!(irq.tpr_lowered().unwrap());
+        assert!(!irq.is_pending());
+        irq.trigger().unwrap();
+        assert_eq!(irq.sink.messages.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_tpr_provider_reentrancy() {
+        let irq = Arc::new(SinkMsiInterrupt::new(
+            InterruptSourceKind::MsiX,
+            RecordingSink::default(),
+        ));
+        irq.update(&MsiIrqConfig::virtio_queue(0, 0x41)).unwrap();
+        irq.enable().unwrap();
+        // The provider calls back into the interrupt, which must not deadlock.
+        let weak = Arc::downgrade(&irq);
+        irq.set_tpr_provider(Arc::new(move || {
+            let irq = weak.upgrade().unwrap();
+            if irq.is_masked().unwrap() {
+                0
+            } else {
+                0x40
+            }
+        }));
+
+        irq.trigger().unwrap();
+        assert!(irq.is_pending());
+        assert!(!irq.tpr_lowered().unwrap());
+        irq.mask().unwrap();
+        assert!(!irq.unmask().unwrap().delivered_pending);
+        assert!(irq.is_pending());
+        assert!(irq.sink.messages.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_sources_targeting() {
+        let group =
+            StaticInterruptGroup::new([(); 3].map(|_| {
+                SinkMsiInterrupt::new(InterruptSourceKind::MsiX, RecordingSink::default())
+            }));
+        for (index, cpu) in [1, 0, 1].iter().enumerate() {
+            let config = MsiIrqConfig {
+                low_addr: 0xfee0_0000 | (cpu << 12),
+                ..Default::default()
+            };
+            group.get(index).unwrap().update(&config).unwrap();
+        }
+
+        assert_eq!(group.sources_targeting(1).unwrap(), vec![0, 2]);
+        assert_eq!(group.sources_targeting(0).unwrap(), vec![1]);
+        assert!(group.sources_targeting(2).unwrap().is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_evacuate_cpu() {
+        let group =
+            StaticInterruptGroup::new([(); 3].map(|_| {
+                SinkMsiInterrupt::new(InterruptSourceKind::MsiX, RecordingSink::default())
+            }));
+        for (index, cpu) in [1, 0, 1].iter().enumerate() {
+            let config = MsiIrqConfig::virtio_queue(*cpu, 0x30 + index as u8);
+            group.get(index).unwrap().update(&config).unwrap();
+        }
+        group.get(2).unwrap().mask().unwrap();
+
+        assert_eq!(group.evacuate_cpu(1, 0x101).unwrap(), vec![0, 2]);
+        assert_eq!(group.sources_targeting(0x101).unwrap(), vec![0, 2]);
+        assert!(group.sources_targeting(1).unwrap().is_empty());
+        assert_eq!(group.evacuate_cpu(0x101, 1).unwrap(), vec![0, 2]);
+        assert_eq!(group.evacuate_cpu(1, 2).unwrap(), vec![0, 2]);
+        assert!(group.sources_targeting(1).unwrap().is_empty());
+        assert_eq!(group.sources_targeting(2).unwrap(), vec![0, 2]);
+        assert_eq!(group.vector(2).unwrap(), 0x32);
+        // The mask state of the interrupts is preserved.
+        assert!(!group.get(0).unwrap().is_masked().unwrap());
+        assert!(group.get(2).unwrap().is_masked().unwrap());
+        assert!(group.evacuate_cpu(1, 2).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_set_target_cpu() {
+        let mut config = MsiIrqConfig::virtio_queue(3, 0x40);
+        config.set_target_cpu(0xff).unwrap();
+        assert_eq!(config.target_cpu(), 0xff);
+        assert_eq!(config.low_addr, 0xfeef_f000);
+
+        // Extended destination ID.
+        config.set_target_cpu(0x1ff).unwrap();
+        assert_eq!(config.target_cpu(), 0x1ff);
+        assert_eq!((config.low_addr, config.high_addr), (0xfeef_f020, 0));
+        // x2APIC format.
+        config.set_target_cpu(0x12345).unwrap();
+        assert_eq!(config.target_cpu(), 0x12345);
+        assert_eq!((config.low_addr, config.high_addr), (0xfee4_5000, 0x12300));
+        config.set_target_cpu(0x3).unwrap();
+        assert_eq!(config, MsiIrqConfig::virtio_queue(3, 0x40));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_partial_updates() {
+        let group = StaticInterruptGroup::new([SinkMsiInterrupt::new(
+            InterruptSourceKind::MsiX,
+            RecordingSink::default(),
+        )]);
+        group
+            .get(0)
+            .unwrap()
+            .update(&MsiIrqConfig {
+                devid: 0x10,
+                ..Default::default()
+            })
+            .unwrap();
+
+        group.update_data(0, 0x41).unwrap();
+        group.update_address(0, 0xfee0_1000, 0x1).unwrap();
+        assert_eq!(
+            group.get(0).unwrap().get_config().unwrap(),
+            MsiIrqConfig {
+                high_addr: 0x1,
+                low_addr: 0xfee0_1000,
+                data: 0x41,
+                devid: 0x10,
+                trigger_mode: TriggerMode::Edge,
+            }
+        );
+        assert!(group.update_data(1, 0x41).is_err());
+    }
+
+    #[test]
+    fn test_pending_bits() {
+        let bits = PendingBits::new(70);
+        assert_eq!(bits.len(), 70);
+        assert!(!bits.is_empty());
+        assert!(PendingBits::new(0).is_empty());
+
+        assert!(!bits.set(3).unwrap());
+        assert!(bits.set(3).unwrap());
+        assert!(!bits.set(65).unwrap());
+        assert!(bits.is_pending(65).unwrap());
+        assert_eq!(bits.qword(0), Some(1 << 3));
+        assert_eq!(bits.qword(1), Some(1 << 1));
+        assert_eq!(bits.qword(2), None);
+
+        assert!(bits.clear(3).unwrap());
+        assert!(!bits.clear(3).unwrap());
+        assert!(!bits.is_pending(3).unwrap());
+        assert!(bits.set(70).is_err());
+        assert!(bits.is_pending(70).is_err());
+    }
+
+    #[test]
+    fn test_pba_access() {
+        let bits = PendingBits::new(70);
+        bits.set(1).unwrap();
+        bits.set(33).unwrap();
+        bits.set(65).unwrap();
+
+        let mut data = [0u8; 8];
+        bits.handle_pba_read(0, &mut data).unwrap();
+        assert_eq!(u64::from_le_bytes(data), (1 << 33) | (1 << 1));
+        let mut data = [0u8; 4];
+        bits.handle_pba_read(4, &mut data).unwrap();
+        assert_eq!(u32::from_le_bytes(data), 1 << 1);
+        bits.handle_pba_read(8, &mut data).unwrap();
+        assert_eq!(u32::from_le_bytes(data), 1 << 1);
+
+        bits.handle_pba_write(0, &[0; 8]);
+        assert!(bits.is_pending(1).unwrap());
+
+        assert!(bits.handle_pba_read(2, &mut [0; 4]).is_err());
+        assert!(bits.handle_pba_read(4, &mut [0; 8]).is_err());
+        assert!(bits.handle_pba_read(16, &mut [0; 8]).is_err());
+    }
+
+    #[test]
+    fn test_next_pending_round_robin() {
+        let bits = PendingBits::new(4);
+        assert_eq!(bits.next_pending_round_robin(), None);
+        assert_eq!(PendingBits::new(0).next_pending_round_robin(), None);
+
+        bits.set(1).unwrap();
+        bits.set(3).unwrap();
+        assert_eq!(bits.next_pending_round_robin(), Some(1));
+        assert_eq!(bits.next_pending_round_robin(), Some(3));
+        assert_eq!(bits.next_pending_round_robin(), Some(1));
+        bits.set(2).unwrap();
+        assert_eq!(bits.next_pending_round_robin(), Some(2));
+        bits.clear(3).unwrap();
+        assert_eq!(bits.next_pending_round_robin(), Some(1));
+    }
+
+    #[test]
+    fn test_pending_bits_ordering() {
+        use std::sync::atomic::AtomicU32;
+        use std::sync::Arc;
+        use std::thread;
+
+        let bits = Arc::new(PendingBits::new(1));
+        let data = Arc::new(AtomicU32::new(0));
+        let writer = {
+            let bits = bits.clone();
+            let data = data.clone();
+            thread::spawn(move || {
+                data.store(0x41, Ordering::Relaxed);
+                bits.set(0).unwrap();
+            })
+        };
+        while !bits.is_pending(0).unwrap() {
+            std::hint::spin_loop();
+        }
+        assert_eq!(data.load(Ordering::Relaxed), 0x41);
+        writer.join().unwrap();
+    }
+}
@@ -0,0 +1,124 @@
+// Copyright (C) 2021 Amazon.com, Inc. or its affiliates.
+// All Rights Reserved.
+
+// SPDX-License-Identifier: Apache-2.0 OR BSD-3-Clause
+
+//! Wrapper that allows referencing the interrupts of a group by name.
+
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use core::ops::Deref;
+
+use crate::interrupt::{EdgeInterrupt, Error, InterruptSourceGroup, Result};
+
+/// An `InterruptSourceGroup` whose interrupts can be looked up by name.
+///
+/// Names are assigned when the interrupts are allocated through `allocate_named()`.
+/// The wrapped group is accessible through `Deref`, so interrupts can still be referenced
+/// by index.
+pub struct NamedInterruptSourceGroup<G: InterruptSourceGroup> {
+    group: G,
+    names: BTreeMap<String, usize>,
+}
+
+impl<G: InterruptSourceGroup> NamedInterruptSourceGroup<G> {
+    /// Create a new named wrapper around `group`.
+    pub fn new(group: G) -> Self {
+        NamedInterruptSourceGroup {
+            group,
+            names: BTreeMap::new(),
+        }
+    }
+
+    /// Allocate one interrupt for each entry in `names`.
+    ///
+    /// The new interrupts are appended to the group in the order of `names`.
+    /// Fails with `Error::InvalidConfiguration` if a name is duplicated or already in use.
+    pub fn allocate_named(&mut self, names: &[&str]) -> Result<()> {
+        for (i, name) in names.iter().enumerate() {
+            if self.names.contains_key(*name) || names[..i].contains(name) {
+                return Err(Error::InvalidConfiguration);
+            }
+        }
+
+        let base = self.group.len();
+        self.group.allocate_interrupts(names.len())?;
+        for (i, name) in names.iter().enumerate() {
+            self.names.insert((*name).to_string(), base + i);
+        }
+        Ok(())
+    }
+
+    /// Release all interrupts within the group, together with their names.
+    pub fn free_interrupts(&mut self) -> Result<()> {
+        self.group.free_interrupts()?;
+        self.names.clear();
+        Ok(())
+    }
+
+    /// Return the index of the interrupt called `name`, or `None` if there is no such interrupt.
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.names.get(name).copied()
+    }
+
+    /// Return the interrupt called `name`, or `None` if there is no such interrupt.
+    pub fn get_by_name(&self, name: &str) -> Option<G::InterruptWrapper> {
+        self.index_of(name).and_then(|index| self.group.get(index))
+    }
+
+    /// Trigger the interrupt called `name`.
+    pub fn trigger_by_name(&self, name: &str) -> Result<()>
+    where
+        G::InterruptType: EdgeInterrupt,
+    {
+        self.get_by_name(name)
+            .ok_or(Error::InvalidConfiguration)?
+            .trigger()
+    }
+
+    /// Consume the wrapper and return the underlying group.
+    pub fn into_inner(self) -> G {
+        self.group
+    }
+}
+
+impl<G: InterruptSourceGroup> Deref for NamedInterruptSourceGroup<G> {
+    type Target = G;
+
+    fn deref(&self) -> &G {
+        &self.group
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::Ordering;
+
+    use crate::interrupt::tests::DummyGroup;
+
+    #[test]
+    fn test_named_group() {
+        let mut group = NamedInterruptSourceGroup::new(DummyGroup::default());
+        group.allocate_named(&["net0-rx", "net0-tx"]).unwrap();
+        group.allocate_named(&["net0-ctrl"]).unwrap();
+        assert_eq!(group.len(), 3);
+        assert_eq!(group.index_of("net0-tx"), Some(1));
+        assert_eq!(group.index_of("net0-ctrl"), Some(2));
+        assert!(group.get_by_name("net0-rx").is_some());
+        assert!(group.get_by_name("net1-rx").is_none());
+
+        group.trigger_by_name("net0-tx").unwrap();
+        assert_eq!(group.get(1).unwrap().triggered.load(Ordering::SeqCst), 1);
+        assert!(group.trigger_by_name("net1-tx").is_err());
+
+        assert!(group.allocate_named(&["net0-rx"]).is_err());
+        assert!(group.allocate_named(&["a", "a"]).is_err());
+        assert_eq!(group.len(), 3);
+
+        group.free_interrupts().unwrap();
+        assert!(group.is_empty());
+        assert!(group.get_by_name("net0-rx").is_none());
+    }
+}
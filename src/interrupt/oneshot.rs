@@ -0,0 +1,275 @@
+// Copyright (C) 2021 Amazon.com, Inc. or its affiliates.
+// All Rights Reserved.
+
+// SPDX-License-Identifier: Apache-2.0 OR BSD-3-Clause
+
+//! Interrupt that disables itself once triggered.
+
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+#[cfg(feature = "configurable")]
+use crate::interrupt::ConfigurableInterrupt;
+use crate::interrupt::{
+    EdgeInterrupt, Interrupt, InterruptId, InterruptSourceKind, OneShotInterrupt, Result,
+};
+#[cfg(feature = "maskable")]
+use crate::interrupt::{MaskableInterrupt, Unmasked};
+
+/// Edge interrupt that can be switched to one-shot mode, where it is disabled after its first
+/// successful trigger until it is enabled again.
+///
+/// This models the completion or timer interrupts that fire once and must be rearmed by the
+/// guest. Triggers of a one-shot interrupt that already fired are dropped until the interrupt
+/// is enabled again, even if the wrapped interrupt doesn't check its enable state, and
+/// `is_enabled()` reports the interrupt as disabled. The other operations are forwarded to the
+/// wrapped interrupt.
+///
+/// Enabling the interrupt while its trigger is being delivered rearms it once the delivery
+/// completes. Failing to disable the wrapped interrupt after delivering it is not reported,
+/// since the interrupt was delivered and further triggers are dropped until it is rearmed.
+pub struct OneShot<I> {
+    inner: I,
+    one_shot: AtomicBool,
+    state: AtomicU8,
+}
+
+// The interrupt can be delivered.
+const ARMED: u8 = 0;
+// A trigger is being delivered.
+const FIRING: u8 = 1;
+// The interrupt was enabled while a trigger was being delivered.
+const REARMED: u8 = 2;
+// The interrupt was delivered and not enabled since.
+const FIRED: u8 = 3;
+
+impl<I: EdgeInterrupt + Interrupt> OneShot<I> {
+    /// Wrap `inner`, initially not in one-shot mode.
+    pub fn new(inner: I) -> Self {
+        OneShot {
+            inner,
+            one_shot: AtomicBool::new(false),
+            state: AtomicU8::new(ARMED),
+        }
+    }
+
+    /// Return whether the interrupt is in one-shot mode.
+    pub fn is_one_shot(&self) -> bool {
+        self.one_shot.load(Ordering::Acquire)
+    }
+
+    /// Return the wrapped interrupt.
+    pub fn inner(&self) -> &I {
+        &self.inner
+    }
+}
+
+impl<I: EdgeInterrupt + Interrupt> Interrupt for OneShot<I> {
+    fn kind(&self) -> InterruptSourceKind {
+        self.inner.kind()
+    }
+
+    fn id(&self) -> InterruptId {
+        self.inner.id()
+    }
+
+    fn enable(&self) -> Result<bool> {
+        // The closure always returns `Some`, so the update can't fail.
+        let _ = self
+            .state
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |state| match state {
+                FIRING | REARMED => Some(REARMED),
+                _ => Some(ARMED),
+            });
+        self.inner.enable()
+    }
+
+    fn disable(&self) -> Result<bool> {
+        self.inner.disable()
+    }
+
+    fn is_enabled(&self) -> Result<bool> {
+        self.inner.is_enabled()
+    }
+}
+
+impl<I: EdgeInterrupt + Interrupt> EdgeInterrupt for OneShot<I> {
+    fn trigger(&self) -> Result<()> {
+        if !self.is_one_shot() {
+            return self.inner.trigger();
+        }
+        // Only one of the concurrent triggers of an armed interrupt is delivered.
+        if self
+            .state
+            .compare_exchange(ARMED, FIRING, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+        {
+            return Ok(());
+        }
+        if let Err(e) = self.inner.trigger() {
+            self.state.store(ARMED, Ordering::Release);
+            return Err(e);
+        }
+        let _ = self.inner.disable();
+        if self
+            .state
+            .compare_exchange(FIRING, FIRED, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+        {
+            // The interrupt was rearmed during the delivery, possibly before it was disabled.
+            self.state.store(ARMED, Ordering::Release);
+            self.inner.enable()?;
+        }
+        Ok(())
+    }
+}
+
+impl<I: EdgeInterrupt + Interrupt> OneShotInterrupt for OneShot<I> {
+    fn set_one_shot(&self, one_shot: bool) -> Result<()> {
+        self.one_shot.store(one_shot, Ordering::Release);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "configurable")]
+impl<I> ConfigurableInterrupt for OneShot<I>
+where
+    I: EdgeInterrupt + ConfigurableInterrupt,
+{
+    type Cfg = I::Cfg;
+
+    fn update(&self, config: &I::Cfg) -> Result<()> {
+        self.inner.update(config)
+    }
+
+    fn validate(&self, config: &I::Cfg) -> Result<()> {
+        self.inner.validate(config)
+    }
+
+    fn get_config(&self) -> Result<I::Cfg> {
+        self.inner.get_config()
+    }
+
+    fn interrupt_line(&self, config: &I::Cfg) -> Option<u32> {
+        self.inner.interrupt_line(config)
+    }
+}
+
+#[cfg(feature = "maskable")]
+impl<I> MaskableInterrupt for OneShot<I>
+where
+    I: EdgeInterrupt + MaskableInterrupt,
+{
+    fn mask(&self) -> Result<bool> {
+        self.inner.mask()
+    }
+
+    fn unmask(&self) -> Result<Unmasked> {
+        self.inner.unmask()
+    }
+
+    fn is_masked(&self) -> Result<bool> {
+        self.inner.is_masked()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::interrupt::fixed::StaticInterruptGroup;
+    use crate::interrupt::tests::DummyInterrupt;
+    use crate::interrupt::InterruptSourceGroup;
+
+    use std::sync::{Arc, Mutex, Weak};
+
+    #[test]
+    fn test_one_shot() {
+        let group = StaticInterruptGroup::new([
+            OneShot::new(DummyInterrupt::default()),
+            OneShot::new(DummyInterrupt::default()),
+        ]);
+        group.enable().unwrap();
+        group.set_one_shot(0, true).unwrap();
+        assert!(group.set_one_shot(2, true).is_err());
+        let irq = group.get(0).unwrap();
+        assert!(irq.is_one_shot());
+
+        irq.trigger().unwrap();
+        irq.trigger().unwrap();
+        assert_eq!(irq.inner().triggered.load(Ordering::SeqCst), 1);
+        assert!(!irq.is_enabled().unwrap());
+
+        irq.enable().unwrap();
+        irq.trigger().unwrap();
+        assert_eq!(irq.inner().triggered.load(Ordering::SeqCst), 2);
+
+        // Other interrupts and interrupts leaving one-shot mode are not disabled.
+        let other = group.get(1).unwrap();
+        other.trigger().unwrap();
+        other.trigger().unwrap();
+        assert_eq!(other.inner().triggered.load(Ordering::SeqCst), 2);
+        assert!(other.is_enabled().unwrap());
+        group.set_one_shot(0, false).unwrap();
+        irq.enable().unwrap();
+        irq.trigger().unwrap();
+        irq.trigger().unwrap();
+        assert_eq!(irq.inner().triggered.load(Ordering::SeqCst), 4);
+        assert!(irq.is_enabled().unwrap());
+    }
+
+    #[test]
+    fn test_rearm_during_trigger() {
+        /// Interrupt rearmed by the guest the first time it is delivered.
+        #[derive(Default)]
+        struct RearmedInterrupt {
+            irq: DummyInterrupt,
+            rearm: Mutex<Option<Weak<OneShot<RearmedInterrupt>>>>,
+        }
+
+        impl Interrupt for RearmedInterrupt {
+            fn kind(&self) -> InterruptSourceKind {
+                self.irq.kind()
+            }
+
+            fn id(&self) -> InterruptId {
+                self.irq.id()
+            }
+
+            fn enable(&self) -> Result<bool> {
+                self.irq.enable()
+            }
+
+            fn disable(&self) -> Result<bool> {
+                self.irq.disable()
+            }
+
+            fn is_enabled(&self) -> Result<bool> {
+                self.irq.is_enabled()
+            }
+        }
+
+        impl EdgeInterrupt for RearmedInterrupt {
+            fn trigger(&self) -> Result<()> {
+                self.irq.trigger()?;
+                let rearm = self.rearm.lock().unwrap().take();
+                if let Some(irq) = rearm.and_then(|irq| irq.upgrade()) {
+                    irq.enable()?;
+                }
+                Ok(())
+            }
+        }
+
+        let irq = Arc::new(OneShot::new(RearmedInterrupt::default()));
+        *irq.inner().rearm.lock().unwrap() = Some(Arc::downgrade(&irq));
+        irq.set_one_shot(true).unwrap();
+        irq.enable().unwrap();
+
+        // The rearm racing with the first delivery is not lost.
+        irq.trigger().unwrap();
+        assert!(irq.is_enabled().unwrap());
+        irq.trigger().unwrap();
+        assert!(!irq.is_enabled().unwrap());
+        irq.trigger().unwrap();
+        assert_eq!(irq.inner().irq.triggered.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,153 @@
+// Copyright (C) 2021 Amazon.com, Inc. or its affiliates.
+// All Rights Reserved.
+
+// SPDX-License-Identifier: Apache-2.0 OR BSD-3-Clause
+
+//! Process-wide registry of interrupt groups for debugging.
+//!
+//! A VMM registers its interrupt groups with `register()` when it creates them, and a monitor
+//! command can then dump the state of all of them with `dump_all()`. The registry only keeps
+//! weak references to the groups, so it doesn't keep them alive: a group leaves the registry
+//! when it is dropped.
+
+use std::sync::{Arc, Mutex, Weak};
+
+use crate::interrupt::InterruptSourceGroup;
+
+/// State of an interrupt group, as reported by `dump_all()`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct GroupSummary {
+    /// Name under which the group was registered.
+    pub name: String,
+    /// Number of interrupts in the group.
+    pub len: usize,
+    /// Enable state of the group, or `None` if the group has no enable state of its own.
+    pub group_enabled: Option<bool>,
+    /// Whether each of the interrupts at the indices `0..len` generates interrupts (see
+    /// `InterruptSourceGroup::effective_enabled()`).
+    pub enabled: Vec<bool>,
+}
+
+trait Summarize: Send + Sync {
+    fn summary(&self, name: &str) -> GroupSummary;
+}
+
+impl<G: InterruptSourceGroup> Summarize for Mutex<G> {
+    fn summary(&self, name: &str) -> GroupSummary {
+        let group = self.lock().expect("failed to acquire lock");
+        let len = group.len();
+        GroupSummary {
+            name: name.to_string(),
+            len,
+            group_enabled: group.is_group_enabled().ok(),
+            enabled: (0..len)
+                .map(|index| group.effective_enabled(index))
+                .collect(),
+        }
+    }
+}
+
+struct Entry {
+    name: String,
+    group: Weak<dyn Summarize>,
+}
+
+static REGISTRY: Mutex<Vec<Entry>> = Mutex::new(Vec::new());
+
+/// Register `group` under `name`.
+///
+/// The group stays in the registry until it is dropped. Several groups can be registered
+/// under the same name.
+pub fn register<G>(name: &str, group: &Arc<Mutex<G>>)
+where
+    G: InterruptSourceGroup + 'static,
+{
+    let group: Arc<dyn Summarize> = group.clone();
+    let mut registry = REGISTRY.lock().expect("failed to acquire lock");
+    registry.retain(|entry| entry.group.strong_count() > 0);
+    registry.push(Entry {
+        name: name.to_string(),
+        group: Arc::downgrade(&group),
+    });
+}
+
+/// Return the summary of each registered group that is still alive, in registration order.
+///
+/// The groups are summarized after releasing the registry, so that a group locked by another
+/// thread doesn't block the registration of other groups.
+pub fn dump_all() -> Vec<GroupSummary> {
+    let groups: Vec<_> = {
+        let mut registry = REGISTRY.lock().expect("failed to acquire lock");
+        registry.retain(|entry| entry.group.strong_count() > 0);
+        registry
+            .iter()
+            .filter_map(|entry| {
+                entry
+                    .group
+                    .upgrade()
+                    .map(|group| (entry.name.clone(), group))
+            })
+            .collect()
+    };
+    groups
+        .iter()
+        .map(|(name, group)| group.summary(name))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::thread;
+    use std::time::Duration;
+
+    use crate::interrupt::tests::DummyGroup;
+
+    #[test]
+    fn test_registry() {
+        let first = Arc::new(Mutex::new(DummyGroup::default()));
+        first.lock().unwrap().allocate_interrupts(2).unwrap();
+        register("test-registry-first", &first);
+        {
+            let second = Arc::new(Mutex::new(DummyGroup::default()));
+            register("test-registry-second", &second);
+            let names: Vec<_> = dump_all().into_iter().map(|s| s.name).collect();
+            assert!(names.contains(&"test-registry-second".to_string()));
+        }
+
+        // Other tests may register groups concurrently.
+        let summaries: Vec<_> = dump_all()
+            .into_iter()
+            .filter(|s| s.name.starts_with("test-registry-"))
+            .collect();
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].name, "test-registry-first");
+        assert_eq!(summaries[0].len, 2);
+        assert_eq!(summaries[0].group_enabled, Some(false));
+        assert_eq!(summaries[0].enabled, vec![false, false]);
+
+        first.lock().unwrap().enable().unwrap();
+        let summary = dump_all()
+            .into_iter()
+            .find(|s| s.name == "test-registry-first")
+            .unwrap();
+        assert_eq!(summary.group_enabled, Some(true));
+    }
+
+    #[test]
+    fn test_register_while_dumping() {
+        let locked = Arc::new(Mutex::new(DummyGroup::default()));
+        register("test-dumping-locked", &locked);
+        let guard = locked.lock().unwrap();
+        // The dump waits for the locked group without holding the registry.
+        let dump = thread::spawn(dump_all);
+        thread::sleep(Duration::from_millis(10));
+        let other = Arc::new(Mutex::new(DummyGroup::default()));
+        register("test-dumping-other", &other);
+        drop(guard);
+
+        let names: Vec<_> = dump.join().unwrap().into_iter().map(|s| s.name).collect();
+        assert!(names.contains(&"test-dumping-locked".to_string()));
+    }
+}
@@ -0,0 +1,116 @@
+// Copyright (C) 2021 Amazon.com, Inc. or its affiliates.
+// All Rights Reserved.
+
+// SPDX-License-Identifier: Apache-2.0 OR BSD-3-Clause
+
+//! Wire-OR status of a level triggered interrupt line shared by several devices.
+
+use std::sync::{Arc, RwLock};
+
+use crate::interrupt::{call_callback, InterruptStatusChecker};
+
+/// Callback through which a device sharing an interrupt line reports whether it still
+/// requires service.
+///
+/// With the `panic-safe-callbacks` feature, a panicking handler is considered to require
+/// service, so that the interrupt of the device is not lost.
+pub type AckHandler = Arc<dyn Fn() -> bool + Send + Sync>;
+
+/// Level triggered interrupt line shared by several devices.
+///
+/// Each device sharing the line registers an ack handler reporting whether it still requires
+/// service. The line implements `InterruptStatusChecker` and is meant to be set as the status
+/// checker of the `AutoRetriggerInterrupt` of the line: when the interrupt is resampled (e.g.
+/// on an EOI), the line stays asserted if any of the ack handlers returns `true`.
+#[derive(Default)]
+pub struct SharedLegacyLine {
+    handlers: RwLock<Vec<AckHandler>>,
+}
+
+impl SharedLegacyLine {
+    /// Create a new shared line without any ack handler.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register the ack handler of a device sharing the line.
+    pub fn register_ack_handler(&self, cb: AckHandler) {
+        self.handlers
+            .write()
+            .expect("failed to acquire lock")
+            .push(cb);
+    }
+}
+
+impl InterruptStatusChecker for SharedLegacyLine {
+    fn is_active(&self) -> bool {
+        // The handlers are called without holding the lock, so that they can register other
+        // handlers.
+        let handlers = self
+            .handlers
+            .read()
+            .expect("failed to acquire lock")
+            .clone();
+        // Evaluate every handler so that all the sharers observe the EOI.
+        handlers.iter().fold(false, |active, handler| {
+            call_callback(|| handler()).unwrap_or(true) | active
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_shared_legacy_line() {
+        let line = SharedLegacyLine::new();
+        assert!(!line.is_active());
+
+        // Each sharer requires service while its counter is not zero.
+        let sharers: Vec<_> = (0..2).map(|_| Arc::new(AtomicUsize::new(0))).collect();
+        for sharer in sharers.iter() {
+            let sharer = sharer.clone();
+            line.register_ack_handler(Arc::new(move || sharer.load(Ordering::SeqCst) != 0));
+        }
+        assert!(!line.is_active());
+
+        sharers[1].store(1, Ordering::SeqCst);
+        assert!(line.is_active());
+        sharers[0].store(1, Ordering::SeqCst);
+        sharers[1].store(0, Ordering::SeqCst);
+        assert!(line.is_active());
+        sharers[0].store(0, Ordering::SeqCst);
+        assert!(!line.is_active());
+    }
+
+    #[test]
+    fn test_register_from_ack_handler() {
+        let line = Arc::new(SharedLegacyLine::new());
+        let weak = Arc::downgrade(&line);
+        line.register_ack_handler(Arc::new(move || {
+            if let Some(line) = weak.upgrade() {
+                line.register_ack_handler(Arc::new(|| true));
+            }
+            false
+        }));
+        // Handlers registered while the line is resampled are evaluated from the next
+        // resample.
+        assert!(!line.is_active());
+        assert!(line.is_active());
+    }
+
+    #[test]
+    #[cfg(feature = "panic-safe-callbacks")]
+    fn test_panicking_ack_handler() {
+        let line = SharedLegacyLine::new();
+        line.register_ack_handler(Arc::new(|| false));
+        line.register_ack_handler(Arc::new(|| panic!("ack handler failure")));
+        assert!(line.is_active());
+        // The lock of the handlers is not poisoned.
+        line.register_ack_handler(Arc::new(|| false));
+        assert!(line.is_active());
+    }
+}
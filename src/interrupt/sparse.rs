@@ -0,0 +1,1009 @@
+// Copyright (C) 2021 Amazon.com, Inc. or its affiliates.
+// All Rights Reserved.
+
+// SPDX-License-Identifier: Apache-2.0 OR BSD-3-Clause
+
+//! Group of interrupts allocated at arbitrary indices.
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::sync::Arc;
+#[cfg(feature = "maskable")]
+use alloc::vec;
+use alloc::vec::Vec;
+#[cfg(feature = "configurable")]
+use core::ops::RangeInclusive;
+#[cfg(feature = "std")]
+use core::time::Duration;
+
+#[cfg(feature = "std")]
+use crate::interrupt::clock::InterruptClock;
+use crate::interrupt::guard::{GuardState, GuardedInterrupt};
+#[cfg(feature = "configurable")]
+use crate::interrupt::legacy::LegacyInterrupt;
+#[cfg(feature = "msi")]
+use crate::interrupt::msi::{MsiInterrupt, MsixLayout, MsixLayoutCell, TriggerMode, TriggerModes};
+use crate::interrupt::{
+    collect_errors, EdgeInterrupt, Error, Interrupt, InterruptSourceGroup, ReplayPolicy, Result,
+};
+#[cfg(feature = "configurable")]
+use crate::interrupt::{ConfigSnapshot, ConfigurableInterrupt};
+#[cfg(feature = "maskable")]
+use crate::interrupt::{MaskSnapshot, MaskableInterrupt};
+
+/// An `InterruptSourceGroup` whose interrupts are allocated individually at arbitrary indices.
+///
+/// This models an MSI-X table whose guest driver only uses some of the vectors, without
+/// allocating interrupts for the unused ones. `get()` returns `None` for the indices without
+/// an interrupt and `len()` counts the allocated interrupts only. The methods of
+/// `InterruptSourceGroup` acting on all the interrupts (e.g. `trigger_all()`) act once on
+/// each allocated interrupt, regardless of its aliases. Mask snapshots and the number of
+/// pending bits cover the indices up to the highest allocated one. Exporting and
+/// importing the configurations as a blob is not supported, and cloning them into another
+/// group with `clone_into_backend()` fails if there are gaps between the indices.
+///
+/// Interrupts are created by a factory called with the index of the new interrupt, and
+/// stored in a `GuardedInterrupt` per index. An interrupt fails to trigger once its index is
+/// released, even through a wrapper returned by `get()` before the release or if another
+/// interrupt was allocated at the same index since. The interrupts also drop their triggers
+/// while the group is disabled with `fast_disable()`, and defer the triggers over the budget
+/// set by `set_group_budget()` until `flush_deferred()` is called.
+///
+/// An index can also be an alias of the interrupt at another index (see `alias()`), to model
+/// devices that deliver several logical interrupts through a single vector.
+pub struct SparseInterruptSourceGroup<I, F> {
+    irqs: BTreeMap<usize, Arc<GuardedInterrupt<I>>>,
+    aliases: BTreeMap<usize, usize>,
+    factory: F,
+    state: Arc<GuardState>,
+    #[cfg(feature = "msi")]
+    msix_layout: MsixLayoutCell,
+}
+
+impl<I, F> SparseInterruptSourceGroup<I, F>
+where
+    I: Interrupt + Send + Sync,
+    F: Fn(usize) -> Result<I> + Send,
+{
+    /// Create a new empty group whose interrupts are created by `factory`.
+    pub fn new(factory: F) -> Self {
+        SparseInterruptSourceGroup {
+            irqs: BTreeMap::new(),
+            aliases: BTreeMap::new(),
+            factory,
+            state: Arc::new(GuardState::new()),
+            #[cfg(feature = "msi")]
+            msix_layout: MsixLayoutCell::default(),
+        }
+    }
+
+    /// Create a new empty group whose interrupts are created by `factory`, and whose budget
+    /// reads the time from `clock`.
+    #[cfg(feature = "std")]
+    pub fn with_clock(factory: F, clock: Arc<dyn InterruptClock>) -> Self {
+        SparseInterruptSourceGroup {
+            state: Arc::new(GuardState::with_clock(clock)),
+            ..Self::new(factory)
+        }
+    }
+
+    /// Allocate an interrupt at `index`.
+    ///
+    /// Fails with `Error::InterruptAllocationError` if there is already an interrupt at
+    /// `index`.
+    pub fn allocate_at(&mut self, index: usize) -> Result<()> {
+        if self.irqs.contains_key(&index) {
+            return Err(Error::InterruptAllocationError);
+        }
+        let irq = (self.factory)(index)?;
+        let irq = GuardedInterrupt::new(Arc::new(irq), self.state.clone());
+        self.irqs.insert(index, Arc::new(irq));
+        Ok(())
+    }
+
+    /// Release the interrupt or the alias at `index`.
+    ///
+    /// An interrupt is disabled before it is released; releasing an alias leaves the interrupt
+    /// it shares untouched. Fails with `Error::InterruptFreeError` if the interrupt at `index`
+    /// still has aliases.
+    pub fn free_at(&mut self, index: usize) -> Result<()> {
+        if self.aliases.values().any(|&to| to == index) {
+            return Err(Error::InterruptFreeError);
+        }
+        let irq = self.irqs.get(&index).ok_or(Error::InterruptFreeError)?;
+        if !self.aliases.contains_key(&index) {
+            disable_for_release(irq.as_ref())?;
+        }
+        if let Some(irq) = self.irqs.remove(&index) {
+            irq.release();
+        }
+        self.aliases.remove(&index);
+        Ok(())
+    }
+
+    /// Make `from` an alias of the interrupt at `to`.
+    ///
+    /// `get(from)` then returns the interrupt at `to`, so triggering the alias delivers the
+    /// interrupt at `to` without allocating backend resources. Since the interrupt is shared,
+    /// configuring, masking or enabling it through the alias is forwarded to the interrupt at
+    /// `to`. Fails with `Error::InterruptAllocationError` if there is already an interrupt at
+    /// `from`, or with `Error::InvalidConfiguration` if there is no interrupt at `to` or if
+    /// `to` is itself an alias.
+    pub fn alias(&mut self, from: usize, to: usize) -> Result<()> {
+        if self.irqs.contains_key(&from) {
+            return Err(Error::InterruptAllocationError);
+        }
+        if self.aliases.contains_key(&to) {
+            return Err(Error::InvalidConfiguration);
+        }
+        let irq = self
+            .irqs
+            .get(&to)
+            .ok_or(Error::InvalidConfiguration)?
+            .shared_inner()
+            .clone();
+        let irq = GuardedInterrupt::new(irq, self.state.clone());
+        self.irqs.insert(from, Arc::new(irq));
+        self.aliases.insert(from, to);
+        Ok(())
+    }
+
+    /// Return the index of the interrupt `index` is an alias of, or `None` if it isn't an
+    /// alias.
+    pub fn alias_target(&self, index: usize) -> Option<usize> {
+        self.aliases.get(&index).copied()
+    }
+
+    // Return the allocated interrupts and their indices, without the aliases.
+    fn entries(&self) -> impl Iterator<Item = (usize, &Arc<GuardedInterrupt<I>>)> + '_ {
+        let aliases = &self.aliases;
+        self.irqs
+            .iter()
+            .filter(move |(index, _)| !aliases.contains_key(index))
+            .map(|(&index, irq)| (index, irq))
+    }
+
+    fn interrupts(&self) -> impl Iterator<Item = &Arc<GuardedInterrupt<I>>> + '_ {
+        self.entries().map(|(_, irq)| irq)
+    }
+
+    // Return the number of indices up to the highest allocated one.
+    #[cfg(feature = "maskable")]
+    fn span(&self) -> usize {
+        self.irqs.keys().next_back().map_or(0, |&index| index + 1)
+    }
+
+    /// Return the indices of the allocated interrupts and aliases in ascending order.
+    pub fn indices(&self) -> impl Iterator<Item = usize> + '_ {
+        self.irqs.keys().copied()
+    }
+
+    /// Deliver the triggers deferred because the budget of the group was exhausted.
+    ///
+    /// This should be called periodically, e.g. once per budget window, so that deferred
+    /// triggers are delivered even if their interrupts are not triggered again. The triggers
+    /// still over the budget stay deferred.
+    #[cfg(feature = "std")]
+    pub fn flush_deferred(&self) -> Result<()>
+    where
+        I: EdgeInterrupt,
+    {
+        collect_errors(self.irqs.values().map(|irq| irq.flush_deferred()))
+    }
+}
+
+// Disable an interrupt about to be released, if it supports it.
+fn disable_for_release<I: Interrupt>(irq: &I) -> Result<()> {
+    match irq.disable() {
+        Ok(_) | Err(Error::OperationNotSupported) => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+impl<I, F> InterruptSourceGroup for SparseInterruptSourceGroup<I, F>
+where
+    I: Interrupt + Send + Sync,
+    F: Fn(usize) -> Result<I> + Send,
+{
+    type InterruptType = GuardedInterrupt<I>;
+    type InterruptWrapper = Arc<GuardedInterrupt<I>>;
+
+    fn len(&self) -> usize {
+        self.irqs.len()
+    }
+
+    /// The gate closed by `fast_disable()` is only opened once all the interrupts are enabled.
+    fn enable(&self) -> Result<()> {
+        collect_errors(self.interrupts().map(|irq| irq.enable().map(|_| ())))?;
+        self.state.open_gate();
+        Ok(())
+    }
+
+    fn disable(&self) -> Result<()> {
+        collect_errors(self.interrupts().map(|irq| irq.disable().map(|_| ())))
+    }
+
+    fn fast_disable(&self) -> Result<()> {
+        self.state.close_gate();
+        Ok(())
+    }
+
+    /// An interrupt counts as fired if it was triggered through any of its aliases.
+    fn never_fired(&self) -> Result<Vec<usize>> {
+        let mut fired = BTreeSet::new();
+        for (&index, irq) in self.irqs.iter() {
+            if irq.has_fired() {
+                fired.insert(self.alias_target(index).unwrap_or(index));
+            }
+        }
+        let mut indices = Vec::new();
+        for (index, irq) in self.entries() {
+            match irq.is_enabled() {
+                Ok(true) if !fired.contains(&index) => indices.push(index),
+                Ok(_) => (),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(indices)
+    }
+
+    #[cfg(feature = "configurable")]
+    fn set_gsi_allowlist(&self, allowed: RangeInclusive<u32>) -> Result<()>
+    where
+        Self::InterruptType: LegacyInterrupt,
+    {
+        self.state.set_gsi_allowlist(allowed);
+        Ok(())
+    }
+
+    #[cfg(feature = "std")]
+    fn set_group_budget(&self, per_window: u32, window: Duration) -> Result<()> {
+        self.state.budget().set_budget(per_window, window);
+        Ok(())
+    }
+
+    fn get(&self, index: usize) -> Option<Arc<GuardedInterrupt<I>>> {
+        self.irqs.get(&index).cloned()
+    }
+
+    fn with<R>(&self, index: usize, f: impl FnOnce(&GuardedInterrupt<I>) -> R) -> Option<R> {
+        self.irqs.get(&index).map(|irq| f(irq))
+    }
+
+    /// Allocate `size` interrupts at the lowest free indices.
+    ///
+    /// If an allocation fails, the interrupts allocated by the call are released.
+    fn allocate_interrupts(&mut self, size: usize) -> Result<()> {
+        let mut allocated = Vec::with_capacity(size);
+        let mut index = 0;
+        let mut result = Ok(());
+        while allocated.len() < size {
+            if !self.irqs.contains_key(&index) {
+                result = self.allocate_at(index);
+                if result.is_err() {
+                    break;
+                }
+                allocated.push(index);
+            }
+            match index.checked_add(1) {
+                Some(next) => index = next,
+                None => {
+                    result = Err(Error::InterruptAllocationError);
+                    break;
+                }
+            }
+        }
+        if result.is_err() {
+            for index in allocated {
+                if let Some(irq) = self.irqs.remove(&index) {
+                    irq.release();
+                }
+            }
+        }
+        result
+    }
+
+    /// The interrupts are disabled, then released from the highest to the lowest index, so
+    /// that the backend resources of an interrupt are dropped with its last index. Unmasking
+    /// requires the interrupts to be maskable, so callers of maskable interrupts should call
+    /// `prepare_free()` first to follow the whole teardown order.
+    fn free_interrupts(&mut self) -> Result<()> {
+        for irq in self.interrupts() {
+            disable_for_release(irq.as_ref())?;
+        }
+        let irqs = core::mem::take(&mut self.irqs);
+        for (_, irq) in irqs.into_iter().rev() {
+            irq.release();
+        }
+        self.aliases.clear();
+        Ok(())
+    }
+
+    #[cfg(feature = "maskable")]
+    fn prepare_free(&self) -> Result<()>
+    where
+        Self::InterruptType: MaskableInterrupt,
+    {
+        for irq in self.interrupts() {
+            irq.disable()?;
+        }
+        for irq in self.interrupts() {
+            irq.unmask()?;
+        }
+        Ok(())
+    }
+
+    fn assert_quiescent(&self) -> Result<()> {
+        if !cfg!(debug_assertions) {
+            return Ok(());
+        }
+        for (index, irq) in self.entries() {
+            match irq.is_enabled() {
+                Ok(true) => return Err(Error::NotQuiescent(index)),
+                Ok(false) | Err(Error::OperationNotSupported) => (),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// The snapshot has an entry for each index returned by `indices()`, aliases included.
+    #[cfg(feature = "configurable")]
+    fn snapshot_configs(
+        &self,
+    ) -> Result<Vec<ConfigSnapshot<<Self::InterruptType as ConfigurableInterrupt>::Cfg>>>
+    where
+        Self::InterruptType: ConfigurableInterrupt,
+    {
+        self.irqs
+            .iter()
+            .map(|(&index, irq)| {
+                Ok(ConfigSnapshot {
+                    config: irq.get_config()?,
+                    enabled: self.effective_enabled(index),
+                })
+            })
+            .collect()
+    }
+
+    #[cfg(feature = "msi")]
+    fn set_msix_layout(&self, layout: MsixLayout) -> Result<()>
+    where
+        Self::InterruptType: MsiInterrupt,
+    {
+        self.msix_layout.set(layout)
+    }
+
+    #[cfg(feature = "msi")]
+    fn msix_layout(&self) -> Option<MsixLayout>
+    where
+        Self::InterruptType: MsiInterrupt,
+    {
+        self.msix_layout.get()
+    }
+
+    #[cfg(feature = "msi")]
+    fn export_blob(&self) -> Result<Vec<u8>>
+    where
+        Self::InterruptType: MsiInterrupt,
+    {
+        Err(Error::OperationNotSupported)
+    }
+
+    #[cfg(feature = "msi")]
+    fn import_blob(&mut self, _blob: &[u8]) -> Result<()>
+    where
+        Self::InterruptType: MsiInterrupt,
+    {
+        Err(Error::OperationNotSupported)
+    }
+
+    #[cfg(feature = "msi")]
+    fn msix_table_size_field(&self) -> u16
+    where
+        Self::InterruptType: MsiInterrupt,
+    {
+        self.span()
+            .saturating_sub(1)
+            .min(crate::interrupt::msi::MSIX_MAX_VECTORS - 1) as u16
+    }
+
+    #[cfg(feature = "msi")]
+    fn sources_targeting(&self, cpu: u32) -> Result<Vec<usize>>
+    where
+        Self::InterruptType: MsiInterrupt,
+    {
+        let mut indices = Vec::new();
+        for (index, irq) in self.entries() {
+            if irq.get_config()?.target_cpu() == cpu {
+                indices.push(index);
+            }
+        }
+        Ok(indices)
+    }
+
+    #[cfg(feature = "msi")]
+    fn trigger_modes(&self) -> Result<TriggerModes>
+    where
+        Self::InterruptType: MsiInterrupt,
+    {
+        let mut modes = TriggerModes::default();
+        for irq in self.interrupts() {
+            match irq.get_config()?.trigger_mode {
+                TriggerMode::Edge => modes.edge = true,
+                TriggerMode::Level => modes.level = true,
+            }
+        }
+        Ok(modes)
+    }
+
+    #[cfg(feature = "maskable")]
+    fn pending_bit_capacity(&self) -> usize
+    where
+        Self::InterruptType: MaskableInterrupt,
+    {
+        self.span()
+    }
+
+    fn trigger_all(&self) -> Result<()>
+    where
+        Self::InterruptType: EdgeInterrupt,
+    {
+        collect_errors(self.interrupts().map(|irq| irq.trigger()))
+    }
+
+    /// With `ReplayPolicy::SuppressOneShot`, the next trigger of each replayed interrupt is
+    /// discarded, through the index it was replayed at.
+    fn replay_fired(&self, fired: &[usize], policy: ReplayPolicy) -> Result<()>
+    where
+        Self::InterruptType: EdgeInterrupt,
+    {
+        collect_errors(fired.iter().map(|&index| {
+            let irq = self.irqs.get(&index).ok_or(Error::InvalidConfiguration)?;
+            irq.trigger()?;
+            if policy == ReplayPolicy::SuppressOneShot {
+                irq.suppress_next_trigger();
+            }
+            Ok(())
+        }))
+    }
+
+    /// The snapshot covers the indices up to the highest allocated one, and the indices
+    /// without an interrupt are reported as masked.
+    #[cfg(feature = "maskable")]
+    fn mask_all_and_snapshot(&self) -> Result<MaskSnapshot>
+    where
+        Self::InterruptType: MaskableInterrupt,
+    {
+        let mut masked = vec![true; self.span()];
+        for (index, irq) in self.entries() {
+            masked[index] = !irq.mask()?;
+        }
+        for (&from, &to) in self.aliases.iter() {
+            masked[from] = masked[to];
+        }
+        Ok(MaskSnapshot { masked })
+    }
+
+    #[cfg(feature = "maskable")]
+    fn restore_mask(&self, snapshot: MaskSnapshot) -> Result<()>
+    where
+        Self::InterruptType: MaskableInterrupt,
+    {
+        if snapshot.masked.len() != self.span() {
+            return Err(Error::InvalidConfiguration);
+        }
+        for (index, irq) in self.entries() {
+            if !snapshot.masked[index] {
+                irq.unmask()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Mutex;
+
+    #[cfg(feature = "std")]
+    use crate::interrupt::clock::ManualClock;
+    #[cfg(feature = "configurable")]
+    use crate::interrupt::legacy::LegacyIrqConfig;
+    use crate::interrupt::tests::DummyInterrupt;
+    use crate::interrupt::{EdgeInterrupt, InterruptId, InterruptSourceKind};
+
+    #[test]
+    fn test_sparse_group() {
+        let mut group = SparseInterruptSourceGroup::new(|_| Ok(DummyInterrupt::default()));
+        assert!(group.is_empty());
+
+        group.allocate_at(3).unwrap();
+        group.allocate_at(7).unwrap();
+        group.allocate_at(31).unwrap();
+        assert!(group.allocate_at(7).is_err());
+        assert_eq!(group.len(), 3);
+        assert!(group.get(0).is_none());
+        assert!(group.get(7).is_some());
+
+        group.enable().unwrap();
+        assert!(group.get(31).unwrap().is_enabled().unwrap());
+
+        group.free_at(7).unwrap();
+        assert!(group.free_at(7).is_err());
+        group.allocate_interrupts(4).unwrap();
+        assert_eq!(group.indices().collect::<Vec<_>>(), vec![0, 1, 2, 3, 4, 31]);
+
+        group.free_interrupts().unwrap();
+        assert!(group.is_empty());
+    }
+
+    #[test]
+    fn test_free_order() {
+        type Log = Arc<Mutex<Vec<(&'static str, usize)>>>;
+
+        /// Interrupt logging when it is disabled and when its resources are dropped.
+        struct RecordingInterrupt {
+            id: InterruptId,
+            index: usize,
+            log: Log,
+        }
+
+        impl Interrupt for RecordingInterrupt {
+            fn kind(&self) -> InterruptSourceKind {
+                InterruptSourceKind::MsiX
+            }
+
+            fn id(&self) -> InterruptId {
+                self.id
+            }
+
+            fn disable(&self) -> Result<bool> {
+                self.log.lock().unwrap().push(("disable", self.index));
+                Ok(true)
+            }
+        }
+
+        impl Drop for RecordingInterrupt {
+            fn drop(&mut self) {
+                self.log.lock().unwrap().push(("drop", self.index));
+            }
+        }
+
+        let log = Log::default();
+        let mut group = {
+            let log = log.clone();
+            SparseInterruptSourceGroup::new(move |index| {
+                Ok(RecordingInterrupt {
+                    id: InterruptId::next(),
+                    index,
+                    log: log.clone(),
+                })
+            })
+        };
+        group.allocate_at(0).unwrap();
+        group.allocate_at(2).unwrap();
+        group.allocate_at(5).unwrap();
+        group.alias(3, 2).unwrap();
+
+        group.free_at(5).unwrap();
+        assert_eq!(*log.lock().unwrap(), vec![("disable", 5), ("drop", 5)]);
+        log.lock().unwrap().clear();
+
+        // The interrupts are disabled before any of them is released, and released from the
+        // highest to the lowest index. The alias keeps the interrupt it shares alive.
+        group.free_interrupts().unwrap();
+        assert!(group.is_empty());
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec![("disable", 0), ("disable", 2), ("drop", 2), ("drop", 0)]
+        );
+    }
+
+    #[test]
+    fn test_alias() {
+        let mut group = SparseInterruptSourceGroup::new(|_| Ok(DummyInterrupt::default()));
+        group.allocate_at(0).unwrap();
+        group.allocate_at(1).unwrap();
+        group.alias(2, 0).unwrap();
+        assert_eq!(group.alias_target(2), Some(0));
+        assert_eq!(group.alias_target(0), None);
+        assert!(group.alias(1, 0).is_err());
+        assert!(group.alias(3, 2).is_err());
+        assert!(group.alias(3, 4).is_err());
+
+        group.get(2).unwrap().trigger().unwrap();
+        assert_eq!(
+            group
+                .get(0)
+                .unwrap()
+                .inner()
+                .triggered
+                .load(Ordering::SeqCst),
+            1
+        );
+        assert_eq!(
+            group
+                .get(1)
+                .unwrap()
+                .inner()
+                .triggered
+                .load(Ordering::SeqCst),
+            0
+        );
+        group.enable().unwrap();
+        assert!(group.get(2).unwrap().is_enabled().unwrap());
+
+        // The interrupt can only be released once its aliases are.
+        assert!(group.free_at(0).is_err());
+        group.free_at(2).unwrap();
+        group.free_at(0).unwrap();
+        assert_eq!(group.indices().collect::<Vec<_>>(), vec![1]);
+    }
+
+    #[test]
+    fn test_fast_disable() {
+        let mut group = SparseInterruptSourceGroup::new(|_| Ok(DummyInterrupt::default()));
+        group.allocate_interrupts(2).unwrap();
+        group.enable().unwrap();
+        let irq = group.get(1).unwrap();
+
+        group.fast_disable().unwrap();
+        irq.trigger().unwrap();
+        group.get(0).unwrap().trigger().unwrap();
+        group.trigger_all().unwrap();
+        group
+            .replay_fired(&[0, 1], ReplayPolicy::ReplayPending)
+            .unwrap();
+        group.with(1, |irq| irq.trigger()).unwrap().unwrap();
+        assert_eq!(irq.inner().triggered.load(Ordering::SeqCst), 0);
+        assert_eq!(
+            group
+                .get(0)
+                .unwrap()
+                .inner()
+                .triggered
+                .load(Ordering::SeqCst),
+            0
+        );
+        // The interrupts themselves stay enabled.
+        assert!(irq.is_enabled().unwrap());
+
+        group.enable().unwrap();
+        irq.trigger().unwrap();
+        assert_eq!(irq.inner().triggered.load(Ordering::SeqCst), 1);
+    }
+
+    // Interrupt whose enable fails while `fail` is set.
+    #[derive(Default)]
+    struct StubbornInterrupt {
+        inner: DummyInterrupt,
+        fail: AtomicBool,
+    }
+
+    impl Interrupt for StubbornInterrupt {
+        fn kind(&self) -> InterruptSourceKind {
+            self.inner.kind()
+        }
+
+        fn id(&self) -> InterruptId {
+            self.inner.id()
+        }
+
+        fn enable(&self) -> Result<bool> {
+            if self.fail.load(Ordering::SeqCst) {
+                return Err(Error::InterruptNotChanged);
+            }
+            self.inner.enable()
+        }
+    }
+
+    impl EdgeInterrupt for StubbornInterrupt {
+        fn trigger(&self) -> Result<()> {
+            self.inner.trigger()
+        }
+    }
+
+    #[test]
+    fn test_fast_disable_failed_enable() {
+        let mut group = SparseInterruptSourceGroup::new(|_| Ok(StubbornInterrupt::default()));
+        group.allocate_interrupts(2).unwrap();
+        group.fast_disable().unwrap();
+        group
+            .get(1)
+            .unwrap()
+            .inner()
+            .fail
+            .store(true, Ordering::SeqCst);
+
+        // The gate stays closed if an interrupt couldn't be enabled.
+        assert!(group.enable().is_err());
+        group.trigger_all().unwrap();
+        assert_eq!(
+            group
+                .get(0)
+                .unwrap()
+                .inner()
+                .inner
+                .triggered
+                .load(Ordering::SeqCst),
+            0
+        );
+
+        group
+            .get(1)
+            .unwrap()
+            .inner()
+            .fail
+            .store(false, Ordering::SeqCst);
+        group.enable().unwrap();
+        group.trigger_all().unwrap();
+        assert_eq!(
+            group
+                .get(0)
+                .unwrap()
+                .inner()
+                .inner
+                .triggered
+                .load(Ordering::SeqCst),
+            1
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_group_budget() {
+        let clock = Arc::new(ManualClock::new());
+        let mut group = SparseInterruptSourceGroup::with_clock(
+            |_| Ok(DummyInterrupt::default()),
+            clock.clone(),
+        );
+        group.allocate_interrupts(2).unwrap();
+        group.set_group_budget(2, Duration::from_millis(1)).unwrap();
+        let (irq0, irq1) = (group.get(0).unwrap(), group.get(1).unwrap());
+        let triggered =
+            |irq: &GuardedInterrupt<DummyInterrupt>| irq.inner().triggered.load(Ordering::SeqCst);
+
+        // The budget is shared by the interrupts of the group.
+        irq0.trigger().unwrap();
+        irq1.trigger().unwrap();
+        irq1.trigger().unwrap();
+        group.trigger_all().unwrap();
+        assert_eq!((triggered(&irq0), triggered(&irq1)), (1, 1));
+        assert!(irq0.is_deferred() && irq1.is_deferred());
+
+        // Deferred triggers are coalesced.
+        clock.advance(Duration::from_millis(1));
+        group.flush_deferred().unwrap();
+        assert_eq!((triggered(&irq0), triggered(&irq1)), (2, 2));
+        assert!(!irq0.is_deferred() && !irq1.is_deferred());
+        group.flush_deferred().unwrap();
+        assert_eq!((triggered(&irq0), triggered(&irq1)), (2, 2));
+
+        group.set_group_budget(0, Duration::ZERO).unwrap();
+        group.trigger_all().unwrap();
+        assert_eq!((triggered(&irq0), triggered(&irq1)), (3, 3));
+    }
+
+    #[test]
+    fn test_replay_fired() {
+        let mut group = SparseInterruptSourceGroup::new(|_| Ok(DummyInterrupt::default()));
+        group.allocate_at(0).unwrap();
+        group.allocate_at(2).unwrap();
+        let triggered = |index| {
+            group
+                .get(index)
+                .unwrap()
+                .inner()
+                .triggered
+                .load(Ordering::SeqCst)
+        };
+
+        group
+            .replay_fired(&[2], ReplayPolicy::ReplayPending)
+            .unwrap();
+        group.get(2).unwrap().trigger().unwrap();
+        assert_eq!(triggered(2), 2);
+
+        // The trigger following the replay is discarded, the next ones are delivered.
+        group
+            .replay_fired(&[0, 2], ReplayPolicy::SuppressOneShot)
+            .unwrap();
+        assert_eq!((triggered(0), triggered(2)), (1, 3));
+        group.trigger_all().unwrap();
+        assert_eq!((triggered(0), triggered(2)), (1, 3));
+        group.trigger_all().unwrap();
+        assert_eq!((triggered(0), triggered(2)), (2, 4));
+
+        assert!(group
+            .replay_fired(&[1], ReplayPolicy::SuppressOneShot)
+            .is_err());
+    }
+
+    #[test]
+    fn test_never_fired() {
+        let mut group = SparseInterruptSourceGroup::new(|_| Ok(DummyInterrupt::default()));
+        group.allocate_at(0).unwrap();
+        group.allocate_at(3).unwrap();
+        group.allocate_at(5).unwrap();
+        group.alias(4, 3).unwrap();
+        assert!(group.never_fired().unwrap().is_empty());
+
+        group.enable().unwrap();
+        group.get(5).unwrap().disable().unwrap();
+        assert_eq!(group.never_fired().unwrap(), vec![0, 3]);
+        group.get(4).unwrap().trigger().unwrap();
+        assert_eq!(group.never_fired().unwrap(), vec![0]);
+
+        // Reallocating an interrupt resets its flag.
+        group.get(0).unwrap().trigger().unwrap();
+        assert!(group.never_fired().unwrap().is_empty());
+        group.free_at(0).unwrap();
+        group.allocate_at(0).unwrap();
+        group.get(0).unwrap().enable().unwrap();
+        assert_eq!(group.never_fired().unwrap(), vec![0]);
+    }
+
+    #[cfg(feature = "configurable")]
+    #[derive(Default)]
+    struct LineInterrupt {
+        id: InterruptId,
+        config: Mutex<LegacyIrqConfig>,
+    }
+
+    #[cfg(feature = "configurable")]
+    impl Interrupt for LineInterrupt {
+        fn kind(&self) -> InterruptSourceKind {
+            InterruptSourceKind::Legacy
+        }
+
+        fn id(&self) -> InterruptId {
+            self.id
+        }
+    }
+
+    #[cfg(feature = "configurable")]
+    impl ConfigurableInterrupt for LineInterrupt {
+        type Cfg = LegacyIrqConfig;
+
+        fn update(&self, config: &LegacyIrqConfig) -> Result<()> {
+            *self.config.lock().unwrap() = *config;
+            Ok(())
+        }
+
+        fn get_config(&self) -> Result<LegacyIrqConfig> {
+            Ok(*self.config.lock().unwrap())
+        }
+
+        fn interrupt_line(&self, config: &LegacyIrqConfig) -> Option<u32> {
+            config.interrupt_line
+        }
+    }
+
+    #[cfg(feature = "configurable")]
+    #[test]
+    fn test_gsi_allowlist() {
+        let mut group = SparseInterruptSourceGroup::new(|_| Ok(LineInterrupt::default()));
+        group.allocate_at(0).unwrap();
+        group.alias(1, 0).unwrap();
+        let irq = group.get(0).unwrap();
+        irq.update(&LegacyIrqConfig::isa(4)).unwrap();
+
+        group.set_gsi_allowlist(16..=23).unwrap();
+        assert!(matches!(
+            irq.update(&LegacyIrqConfig::isa(24)),
+            Err(Error::InvalidConfiguration)
+        ));
+        assert!(group
+            .get(1)
+            .unwrap()
+            .update(&LegacyIrqConfig::isa(4))
+            .is_err());
+        assert!(irq.validate(&LegacyIrqConfig::isa(15)).is_err());
+        assert_eq!(irq.get_config().unwrap(), LegacyIrqConfig::isa(4));
+
+        irq.update(&LegacyIrqConfig::isa(16)).unwrap();
+        group
+            .get(1)
+            .unwrap()
+            .update(&LegacyIrqConfig::zeroed())
+            .unwrap();
+        assert_eq!(irq.get_config().unwrap(), LegacyIrqConfig::zeroed());
+    }
+
+    #[test]
+    fn test_sparse_group_defaults() {
+        let mut group = SparseInterruptSourceGroup::new(|_| Ok(DummyInterrupt::default()));
+        group.allocate_at(5).unwrap();
+        group.alias(2, 5).unwrap();
+        group.trigger_all().unwrap();
+        assert_eq!(
+            group
+                .get(5)
+                .unwrap()
+                .inner()
+                .triggered
+                .load(Ordering::SeqCst),
+            1
+        );
+
+        group.assert_quiescent().unwrap();
+        group.enable().unwrap();
+        if cfg!(debug_assertions) {
+            assert!(matches!(
+                group.assert_quiescent(),
+                Err(Error::NotQuiescent(5))
+            ));
+        }
+
+        #[cfg(feature = "configurable")]
+        assert_eq!(group.snapshot_configs().unwrap().len(), 2);
+
+        #[cfg(feature = "maskable")]
+        {
+            assert_eq!(group.pending_bit_capacity(), 6);
+            let snapshot = group.mask_all_and_snapshot().unwrap();
+            assert_eq!(snapshot.is_masked(5), Some(false));
+            assert_eq!(snapshot.is_masked(2), Some(false));
+            assert_eq!(snapshot.is_masked(0), Some(true));
+            assert!(group.get(5).unwrap().is_masked().unwrap());
+            group.restore_mask(snapshot).unwrap();
+            assert!(!group.get(5).unwrap().is_masked().unwrap());
+
+            group.disable().unwrap();
+            group.prepare_free().unwrap();
+            group.assert_quiescent().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_allocate_rollback() {
+        let mut group = SparseInterruptSourceGroup::new(|index| {
+            if index == 3 {
+                Err(Error::InterruptAllocationError)
+            } else {
+                Ok(DummyInterrupt::default())
+            }
+        });
+        group.allocate_at(1).unwrap();
+        assert!(group.allocate_interrupts(3).is_err());
+        assert_eq!(group.indices().collect::<Vec<_>>(), vec![1]);
+        group.allocate_interrupts(2).unwrap();
+        assert_eq!(group.indices().collect::<Vec<_>>(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_stale_interrupt() {
+        let mut group = SparseInterruptSourceGroup::new(|_| Ok(DummyInterrupt::default()));
+        group.allocate_at(0).unwrap();
+        group.allocate_at(1).unwrap();
+        group.alias(2, 1).unwrap();
+        let stale = group.get(0).unwrap();
+        let other = group.get(1).unwrap();
+        let alias = group.get(2).unwrap();
+        stale.trigger().unwrap();
+        assert!(!stale.is_released());
+
+        group.free_at(0).unwrap();
+        group.allocate_at(0).unwrap();
+        assert!(stale.is_released());
+        assert!(matches!(stale.trigger(), Err(Error::InvalidConfiguration)));
+        assert_eq!(stale.inner().triggered.load(Ordering::SeqCst), 1);
+
+        // Releasing an index doesn't affect the interrupts at the other indices.
+        other.trigger().unwrap();
+        group.free_at(2).unwrap();
+        assert!(alias.trigger().is_err());
+        other.trigger().unwrap();
+        assert_eq!(other.inner().triggered.load(Ordering::SeqCst), 2);
+
+        let irq = group.get(0).unwrap();
+        irq.trigger().unwrap();
+        assert_eq!(irq.inner().triggered.load(Ordering::SeqCst), 1);
+        group.free_interrupts().unwrap();
+        assert!(irq.trigger().is_err());
+        assert!(other.trigger().is_err());
+    }
+}
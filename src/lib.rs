@@ -2,6 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0 OR BSD-3-Clause
 
 #![deny(missing_docs)]
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 
 //! This crate provides:
 //! * device traits defining read and write operations on specialized buses
@@ -21,6 +22,8 @@
 //! [`IoManager`](device_manager/struct.IoManager.html)
 //! and dispatch a write operation to the device.
 //!```
+//! # #[cfg(feature = "std")]
+//! # {
 //! use std::sync::{Arc, Mutex};
 //! use vm_device::bus::{PioAddress, PioAddressOffset, PioRange};
 //! use vm_device::device_manager::{IoManager, PioManager};
@@ -48,15 +51,20 @@
 //!     .register_pio(bus_range, Arc::new(Mutex::new(device)))
 //!     .unwrap();
 //! manager.pio_write(PioAddress(0), &vec![b'o', b'k']).unwrap();
+//! # }
 //! ```
 
+extern crate alloc;
+
 pub mod bus;
 pub mod device_manager;
 pub mod interrupt;
 pub mod resources;
 
-use std::ops::Deref;
-use std::sync::{Arc, Mutex};
+use alloc::sync::Arc;
+use core::ops::Deref;
+#[cfg(feature = "std")]
+use std::sync::Mutex;
 
 use bus::{MmioAddress, MmioAddressOffset, PioAddress, PioAddressOffset};
 
@@ -267,6 +275,7 @@ impl<T: DevicePio + ?Sized> DevicePio for Arc<T> {
 
 // Blanket implementations for Mutex<T>.
 
+#[cfg(feature = "std")]
 impl<T: MutDeviceMmio + ?Sized> DeviceMmio for Mutex<T> {
     fn mmio_read(&self, base: MmioAddress, offset: MmioAddressOffset, data: &mut [u8]) {
         self.lock().unwrap().mmio_read(base, offset, data)
@@ -277,6 +286,7 @@ impl<T: MutDeviceMmio + ?Sized> DeviceMmio for Mutex<T> {
     }
 }
 
+#[cfg(feature = "std")]
 impl<T: MutDevicePio + ?Sized> DevicePio for Mutex<T> {
     fn pio_read(&self, base: PioAddress, offset: PioAddressOffset, data: &mut [u8]) {
         self.lock().unwrap().pio_read(base, offset, data)
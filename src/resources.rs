@@ -12,7 +12,9 @@
 //! 5) the VMM registers the new device onto corresponding device managers according the allocated
 //!    resources.
 
-use std::{u16, u32, u64};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::{u16, u32, u64};
 
 /// Enumeration describing a device's resource constraints.
 pub enum ResourceConstraint {